@@ -0,0 +1,753 @@
+//! Streams wrapping the underlying stream of a shared stream, so their work is done once for all
+//! handles.
+
+use core::fmt;
+#[cfg(feature = "std")]
+use core::mem::ManuallyDrop;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+#[cfg(feature = "std")]
+use std::thread::{self, ThreadId};
+
+pin_project! {
+    /// Stream for the [`shared_lazy`](crate::shared_lazy) and
+    /// [`ashared_lazy`](crate::ashared_lazy) functions.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Lazy<F, S> {
+        init: Option<F>,
+        #[pin]
+        stream: Option<S>,
+    }
+}
+
+impl<F, S> Lazy<F, S> {
+    pub(crate) const fn new(init: F) -> Self {
+        Self {
+            init: Some(init),
+            stream: None,
+        }
+    }
+}
+
+impl<F, S: fmt::Debug> fmt::Debug for Lazy<F, S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lazy")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<F: FnOnce() -> S, S: Stream> Stream for Lazy<F, S> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if let Some(init) = this.init.take() {
+            this.stream.set(Some(init()));
+        }
+        this.stream.as_pin_mut().unwrap().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.as_ref().map_or((0, None), Stream::size_hint)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_intersperse`](crate::Share::shared_intersperse) method.
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Intersperse<S: Stream> {
+        #[pin]
+        stream: S,
+        separator: S::Item,
+        // item that has been polled and is yielded after the next separator
+        next: Option<S::Item>,
+        started: bool,
+    }
+}
+
+impl<S: Stream> Intersperse<S> {
+    pub(crate) const fn new(stream: S, separator: S::Item) -> Self {
+        Self {
+            stream,
+            separator,
+            next: None,
+            started: false,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Intersperse<S>
+where
+    S::Item: Clone,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if let Some(v) = this.next.take() {
+            return Poll::Ready(Some(v));
+        }
+        this.stream.poll_next(cx).map(|v| {
+            v.map(|v| {
+                if *this.started {
+                    *this.next = Some(v);
+                    this.separator.clone()
+                } else {
+                    *this.started = true;
+                    v
+                }
+            })
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = usize::from(self.next.is_some());
+        // every upcoming item is preceded by a separator, except for the very first one
+        let with_separators = |n: usize| {
+            let n = n.checked_mul(2)?;
+            if self.started {
+                n.checked_add(pending)
+            } else {
+                Some(n.saturating_sub(1))
+            }
+        };
+        let (lower, upper) = self.stream.size_hint();
+        (
+            with_separators(lower).unwrap_or(usize::MAX),
+            upper.and_then(with_separators),
+        )
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_coalesce`](crate::Share::shared_coalesce) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Coalesce<S: Stream, F> {
+        #[pin]
+        stream: S,
+        f: F,
+        // item that adjacent items are merged into until `f` rejects one
+        acc: Option<S::Item>,
+        done: bool,
+    }
+}
+
+impl<S: Stream, F> Coalesce<S, F> {
+    pub(crate) const fn new(stream: S, f: F) -> Self {
+        Self {
+            stream,
+            f,
+            acc: None,
+            done: false,
+        }
+    }
+}
+
+impl<S, F> fmt::Debug for Coalesce<S, F>
+where
+    S: Stream + fmt::Debug,
+    S::Item: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Coalesce")
+            .field("stream", &self.stream)
+            .field("acc", &self.acc)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F> Stream for Coalesce<S, F>
+where
+    S: Stream,
+    F: FnMut(S::Item, S::Item) -> Result<S::Item, (S::Item, S::Item)>,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if *this.done {
+                return Poll::Ready(this.acc.take());
+            }
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(v) => match this.acc.take() {
+                    Some(acc) => match (this.f)(acc, v) {
+                        Ok(merged) => *this.acc = Some(merged),
+                        Err((acc, v)) => {
+                            *this.acc = Some(v);
+                            return Poll::Ready(Some(acc));
+                        }
+                    },
+                    None => *this.acc = Some(v),
+                },
+                None => *this.done = true,
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let pending = usize::from(self.acc.is_some());
+        if self.done {
+            return (pending, Some(pending));
+        }
+        // all upcoming items might be merged into one
+        let (lower, upper) = self.stream.size_hint();
+        (
+            usize::from(pending + lower > 0),
+            upper.and_then(|upper| upper.checked_add(pending)),
+        )
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_dedup_by_key`](crate::Share::shared_dedup_by_key) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct DedupByKey<S, F, K> {
+        #[pin]
+        stream: S,
+        f: F,
+        // key of the last yielded item
+        last: Option<K>,
+    }
+}
+
+impl<S, F, K> DedupByKey<S, F, K> {
+    pub(crate) const fn new(stream: S, f: F) -> Self {
+        Self {
+            stream,
+            f,
+            last: None,
+        }
+    }
+}
+
+impl<S: fmt::Debug, F, K: fmt::Debug> fmt::Debug for DedupByKey<S, F, K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DedupByKey")
+            .field("stream", &self.stream)
+            .field("last", &self.last)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F, K> Stream for DedupByKey<S, F, K>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> K,
+    K: PartialEq,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let Some(v) = ready!(this.stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            let key = (this.f)(&v);
+            if this.last.as_ref() != Some(&key) {
+                *this.last = Some(key);
+                return Poll::Ready(Some(v));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.stream.size_hint();
+        // all upcoming items might have the same key as the last one
+        (usize::from(self.last.is_none() && lower > 0), upper)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_dedup`](crate::Share::shared_dedup) method.
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Dedup<S: Stream> {
+        #[pin]
+        stream: S,
+        // last yielded item
+        last: Option<S::Item>,
+    }
+}
+
+impl<S: Stream> Dedup<S> {
+    pub(crate) const fn new(stream: S) -> Self {
+        Self { stream, last: None }
+    }
+}
+
+impl<S: Stream> Stream for Dedup<S>
+where
+    S::Item: PartialEq + Clone,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let Some(v) = ready!(this.stream.as_mut().poll_next(cx)) else {
+                return Poll::Ready(None);
+            };
+            if this.last.as_ref() != Some(&v) {
+                *this.last = Some(v.clone());
+                return Poll::Ready(Some(v));
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.stream.size_hint();
+        // all upcoming items might equal the last one
+        (usize::from(self.last.is_none() && lower > 0), upper)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_take_while`](crate::Share::shared_take_while) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct TakeWhile<S, F> {
+        #[pin]
+        stream: S,
+        f: F,
+        done: bool,
+    }
+}
+
+impl<S, F> TakeWhile<S, F> {
+    pub(crate) const fn new(stream: S, f: F) -> Self {
+        Self {
+            stream,
+            f,
+            done: false,
+        }
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for TakeWhile<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TakeWhile")
+            .field("stream", &self.stream)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F> Stream for TakeWhile<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> bool,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+        let item = ready!(this.stream.poll_next(cx)).filter(|v| (this.f)(v));
+        *this.done = item.is_none();
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        // the very next item might be rejected
+        (0, self.stream.size_hint().1)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_take`](crate::Share::shared_take) method.
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Take<S> {
+        // dropped as soon as the last item has been taken
+        #[pin]
+        stream: Option<S>,
+        remaining: usize,
+    }
+}
+
+impl<S> Take<S> {
+    pub(crate) fn new(stream: S, n: usize) -> Self {
+        Self {
+            stream: (n > 0).then_some(stream),
+            remaining: n,
+        }
+    }
+}
+
+impl<S: Stream> Stream for Take<S> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let Some(stream) = this.stream.as_mut().as_pin_mut() else {
+            return Poll::Ready(None);
+        };
+        let item = ready!(stream.poll_next(cx));
+        if item.is_some() {
+            *this.remaining -= 1;
+        }
+        if item.is_none() || *this.remaining == 0 {
+            this.stream.set(None);
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let Some(stream) = &self.stream else {
+            return (0, Some(0));
+        };
+        let (lower, upper) = stream.size_hint();
+        (
+            lower.min(self.remaining),
+            Some(upper.map_or(self.remaining, |upper| upper.min(self.remaining))),
+        )
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_scan`](crate::Share::shared_scan) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Scan<S, St, F> {
+        #[pin]
+        stream: S,
+        state: St,
+        f: F,
+        done: bool,
+    }
+}
+
+impl<S, St, F> Scan<S, St, F> {
+    pub(crate) const fn new(stream: S, state: St, f: F) -> Self {
+        Self {
+            stream,
+            state,
+            f,
+            done: false,
+        }
+    }
+}
+
+impl<S: fmt::Debug, St: fmt::Debug, F> fmt::Debug for Scan<S, St, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scan")
+            .field("stream", &self.stream)
+            .field("state", &self.state)
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, St, F, T> Stream for Scan<S, St, F>
+where
+    S: Stream,
+    F: FnMut(&mut St, S::Item) -> Option<T>,
+{
+    type Item = T;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.done {
+            return Poll::Ready(None);
+        }
+        let item = ready!(this.stream.poll_next(cx)).and_then(|v| (this.f)(this.state, v));
+        *this.done = item.is_none();
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.done {
+            return (0, Some(0));
+        }
+        // `f` might end the stream with the very next item
+        (0, self.stream.size_hint().1)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_filter`](crate::Share::shared_filter) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct Filter<S, F> {
+        #[pin]
+        stream: S,
+        f: F,
+    }
+}
+
+impl<S, F> Filter<S, F> {
+    pub(crate) const fn new(stream: S, f: F) -> Self {
+        Self { stream, f }
+    }
+}
+
+impl<S: fmt::Debug, F> fmt::Debug for Filter<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S, F> Stream for Filter<S, F>
+where
+    S: Stream,
+    F: FnMut(&S::Item) -> bool,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            match ready!(this.stream.as_mut().poll_next(cx)) {
+                Some(v) if !(this.f)(&v) => {}
+                item => return Poll::Ready(item),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // every item might be rejected
+        (0, self.stream.size_hint().1)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`try_shared`](crate::Share::try_shared) method.
+    #[derive(Debug)]
+    #[must_use = "streams do nothing unless polled"]
+    pub struct UntilErr<S> {
+        #[pin]
+        stream: S,
+        errored: bool,
+    }
+}
+
+impl<S> UntilErr<S> {
+    pub(crate) const fn new(stream: S) -> Self {
+        Self {
+            stream,
+            errored: false,
+        }
+    }
+}
+
+impl<S, T, E> Stream for UntilErr<S>
+where
+    S: Stream<Item = Result<T, E>>,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        if *this.errored {
+            return Poll::Ready(None);
+        }
+        let item = ready!(this.stream.poll_next(cx));
+        *this.errored = matches!(item, Some(Err(_)));
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.errored {
+            return (0, Some(0));
+        }
+        // the very next item might be an error
+        let (lower, upper) = self.stream.size_hint();
+        (lower.min(1), upper)
+    }
+}
+
+pin_project! {
+    /// Stream for the [`shared_with_drop`](crate::Share::shared_with_drop) method.
+    #[must_use = "streams do nothing unless polled"]
+    pub struct OnDrop<S, F: FnOnce()> {
+        // only `None` while being dropped, so `on_drop` runs after the stream has been dropped
+        #[pin]
+        stream: Option<S>,
+        on_drop: Option<F>,
+    }
+
+    impl<S, F: FnOnce()> PinnedDrop for OnDrop<S, F> {
+        fn drop(this: Pin<&mut Self>) {
+            let mut this = this.project();
+            this.stream.set(None);
+            if let Some(on_drop) = this.on_drop.take() {
+                on_drop();
+            }
+        }
+    }
+}
+
+impl<S, F: FnOnce()> OnDrop<S, F> {
+    pub(crate) const fn new(stream: S, on_drop: F) -> Self {
+        Self {
+            stream: Some(stream),
+            on_drop: Some(on_drop),
+        }
+    }
+}
+
+impl<S: fmt::Debug, F: FnOnce()> fmt::Debug for OnDrop<S, F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OnDrop")
+            .field("stream", &self.stream)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<S: Stream, F: FnOnce()> Stream for OnDrop<S, F> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().stream.as_pin_mut().unwrap().poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.stream.as_ref().map_or((0, Some(0)), Stream::size_hint)
+    }
+}
+
+/// Stream for the [`ashared_local`](crate::Share::ashared_local) method, which may only be
+/// polled on the thread that created it.
+#[cfg(feature = "std")]
+#[must_use = "streams do nothing unless polled"]
+pub struct ThreadBound<S> {
+    thread: ThreadId,
+    // only dropped on `thread`, and leaked if this is dropped on another one
+    stream: ManuallyDrop<S>,
+}
+
+// SAFETY: the stream is only ever touched on `thread`. Everything reachable from another thread
+// checks the thread first or doesn't touch the stream:
+// * through `&ThreadBound`, e.g. by handles on other threads: `size_hint` returns `(0, None)`
+//   off the thread, and `Debug` only formats `thread`,
+// * through `Pin<&mut ThreadBound>`: `poll_next` panics off the thread,
+// * by value: dropping it off the thread leaks the stream instead of dropping it.
+// Items aren't covered by this, but they are only handed out through `Ashared`, which is only
+// `Send` and `Sync` if they are.
+#[cfg(feature = "std")]
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl<S> Send for ThreadBound<S> {}
+#[cfg(feature = "std")]
+unsafe impl<S> Sync for ThreadBound<S> {}
+
+#[cfg(feature = "std")]
+impl<S> ThreadBound<S> {
+    pub(crate) fn new(stream: S) -> Self {
+        Self {
+            thread: thread::current().id(),
+            stream: ManuallyDrop::new(stream),
+        }
+    }
+
+    fn is_on_thread(&self) -> bool {
+        thread::current().id() == self.thread
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> Drop for ThreadBound<S> {
+    fn drop(&mut self) {
+        if self.is_on_thread() {
+            // the stream is dropped in place, so this is fine even if it has been pinned
+            unsafe { ManuallyDrop::drop(&mut self.stream) }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S> fmt::Debug for ThreadBound<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ThreadBound")
+            .field("thread", &self.thread)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<S: Stream> Stream for ThreadBound<S> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        assert!(
+            self.is_on_thread(),
+            "underlying stream polled off the thread that created it"
+        );
+        // the stream is pinned whenever `self` is, since it is never moved out
+        unsafe { self.map_unchecked_mut(|this| &mut *this.stream) }.poll_next(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        if self.is_on_thread() {
+            self.stream.size_hint()
+        } else {
+            (0, None)
+        }
+    }
+}
+
+/// Stream for the `Ok` side of the [`shared_split`](crate::Share::shared_split) method.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitOk<H> {
+    handle: H,
+}
+
+impl<H> SplitOk<H> {
+    pub(crate) const fn new(handle: H) -> Self {
+        Self { handle }
+    }
+}
+
+impl<H, T, E> Stream for SplitOk<H>
+where
+    H: Stream<Item = Result<T, E>> + Unpin,
+{
+    type Item = T;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(Pin::new(&mut self.handle).poll_next(cx)) {
+                Some(Ok(v)) => return Poll::Ready(Some(v)),
+                Some(Err(_)) => {}
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // all upcoming items might be errors
+        (0, self.handle.size_hint().1)
+    }
+}
+
+/// Stream for the `Err` side of the [`shared_split`](crate::Share::shared_split) method.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct SplitErr<H> {
+    handle: H,
+}
+
+impl<H> SplitErr<H> {
+    pub(crate) const fn new(handle: H) -> Self {
+        Self { handle }
+    }
+}
+
+impl<H, T, E> Stream for SplitErr<H>
+where
+    H: Stream<Item = Result<T, E>> + Unpin,
+{
+    type Item = E;
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            match ready!(Pin::new(&mut self.handle).poll_next(cx)) {
+                Some(Err(e)) => return Poll::Ready(Some(e)),
+                Some(Ok(_)) => {}
+                None => return Poll::Ready(None),
+            }
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        // all upcoming items might be `Ok`
+        (0, self.handle.size_hint().1)
+    }
+}
@@ -0,0 +1,72 @@
+//! Shared streams whose items are pushed by hand.
+
+use crate::state::InnerState;
+use crate::{Local, Shared};
+use alloc::rc::{Rc, Weak};
+use core::cell::RefCell;
+use core::fmt;
+use core::pin::Pin;
+use futures_util::stream::{self, Pending};
+use futures_util::task::ArcWake;
+
+/// Creates a shared stream whose items are pushed with the returned sender instead of being
+/// produced by an underlying stream.
+///
+/// This works like a broadcast channel that replays all items to new handles, which is handy to
+/// feed handles deterministically in tests. The stream ends once the sender is closed or
+/// dropped.
+pub fn shared_channel<T: Clone>() -> (SharedSender<T>, Shared<Pending<T>>) {
+    let shared = Shared::new(stream::pending());
+    let sender = SharedSender {
+        inner: Rc::downgrade(&shared.inner),
+    };
+    (sender, shared)
+}
+
+/// Sender for the [`shared_channel`] function.
+pub struct SharedSender<T> {
+    // doesn't keep the state alive, so pushing items without any handles left does nothing
+    inner: Weak<RefCell<InnerState<Pending<T>, Local>>>,
+}
+
+impl<T> SharedSender<T> {
+    /// Pushes an item to all handles, waking the ones waiting for it.
+    ///
+    /// Does nothing once the shared stream has ended, e.g. after
+    /// [`close_source`](crate::SharedStream::close_source), since handles have already yielded
+    /// its end.
+    pub fn push(&self, item: T) {
+        self.with_state(|inner| {
+            if inner.stream.is_some() {
+                inner.push(item);
+            }
+        });
+    }
+
+    /// Ends the shared stream, which happens as well when the sender is dropped.
+    pub fn close(self) {
+        drop(self);
+    }
+
+    fn with_state(&self, f: impl FnOnce(Pin<&mut InnerState<Pending<T>, Local>>)) {
+        let Some(inner) = self.inner.upgrade() else {
+            return;
+        };
+        let mut inner = inner.borrow_mut();
+        // the state is never moved out of the `Rc`, see `SharedStream::poll_item`
+        f(unsafe { Pin::new_unchecked(&mut *inner) });
+        ArcWake::wake_by_ref(&inner.waker);
+    }
+}
+
+impl<T> Drop for SharedSender<T> {
+    fn drop(&mut self) {
+        self.with_state(InnerState::finish);
+    }
+}
+
+impl<T> fmt::Debug for SharedSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSender").finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,89 @@
+//! Opt-in policy for growing the buffer ahead of time.
+
+// `pub(crate)` is required by the `unreachable_pub` lint
+#![allow(clippy::redundant_pub_crate)]
+
+use alloc::vec::Vec;
+
+/// Policy for reserving buffer capacity before it runs out, used by
+/// [`shared_with_growth_policy`](crate::Share::shared_with_growth_policy).
+///
+/// Whenever the buffer is filled beyond the threshold, capacity for `factor` times the number of
+/// items produced since the last reservation is reserved, but at least for `min_reserve` items.
+/// This spreads reallocations out for sources producing at a steady rate, instead of only
+/// reallocating once the buffer is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GrowthPolicy {
+    threshold_percent: usize,
+    factor: usize,
+    min_reserve: usize,
+}
+
+impl GrowthPolicy {
+    /// Creates a policy reserving `factor` times the recent growth once the buffer is 75% full,
+    /// and at least 16 items.
+    #[must_use]
+    pub const fn new(factor: usize) -> Self {
+        Self {
+            threshold_percent: 75,
+            factor,
+            min_reserve: 16,
+        }
+    }
+
+    /// Sets how full the buffer has to be, in percent of its capacity, before more capacity is
+    /// reserved.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percent` is greater than 100.
+    #[must_use]
+    pub const fn threshold_percent(mut self, percent: usize) -> Self {
+        assert!(percent <= 100, "threshold must be at most 100%");
+        self.threshold_percent = percent;
+        self
+    }
+
+    /// Sets the minimum number of items to reserve capacity for.
+    #[must_use]
+    pub const fn min_reserve(mut self, n: usize) -> Self {
+        self.min_reserve = n;
+        self
+    }
+}
+
+/// A [`GrowthPolicy`] along with the state it needs.
+#[derive(Debug)]
+pub(crate) struct Growth {
+    policy: GrowthPolicy,
+    // buffer length at the last reservation
+    reserved_at: usize,
+}
+
+impl Growth {
+    pub(crate) const fn new(policy: GrowthPolicy) -> Self {
+        Self {
+            policy,
+            reserved_at: 0,
+        }
+    }
+
+    /// Reserves capacity in `values` if the policy asks for it. Called after every push.
+    pub(crate) fn grow<T>(&mut self, values: &mut Vec<T>) {
+        let len = values.len();
+        // the buffer has been cleared since the last reservation
+        self.reserved_at = self.reserved_at.min(len);
+        let threshold = values.capacity() / 100 * self.policy.threshold_percent
+            + values.capacity() % 100 * self.policy.threshold_percent / 100;
+        if len < threshold {
+            return;
+        }
+        let recent = len - self.reserved_at;
+        values.reserve(
+            recent
+                .saturating_mul(self.policy.factor)
+                .max(self.policy.min_reserve),
+        );
+        self.reserved_at = len;
+    }
+}
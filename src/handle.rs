@@ -0,0 +1,142 @@
+//! [`SharedHandle`], which is either a [`Shared`] or an [`Ashared`].
+
+use crate::{Ashared, Shared};
+use core::fmt;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::{FusedStream, Stream};
+use futures_util::stream::StreamExt;
+
+/// Either a [`Shared`] or an [`Ashared`] handle, for generic code that works with both.
+///
+/// Whether a stream is `Send` can't be detected without specialization, so the variant can't be
+/// picked automatically. Instead, generic code takes a [`ShareMode`] type parameter which the
+/// caller, who knows the concrete stream type, sets to [`Local`] or [`ThreadSafe`].
+///
+/// Since one of the variants holds a [`Shared`], a `SharedHandle` is never `Send` itself. Match on
+/// it to get the [`Ashared`] out before moving it to another thread.
+///
+/// ```
+/// # futures::executor::block_on(async {
+/// use futures::stream::{self, Stream, StreamExt};
+/// use shared_stream::{Local, Share, ShareMode, SharedHandle, ThreadSafe};
+///
+/// fn fan_out<M: ShareMode<S>, S: Stream>(stream: S) -> [SharedHandle<S>; 2]
+/// where
+///     S::Item: Clone,
+/// {
+///     let handle = stream.shared_with_mode::<M>();
+///     [handle.clone(), handle]
+/// }
+///
+/// let [a, b] = fan_out::<ThreadSafe, _>(stream::iter(1..=3));
+/// assert_eq!(a.collect::<Vec<_>>().await, [1, 2, 3]);
+/// assert_eq!(b.collect::<Vec<_>>().await, [1, 2, 3]);
+/// let [a, _] = fan_out::<Local, _>(stream::iter(1..=3));
+/// assert_eq!(a.collect::<Vec<_>>().await, [1, 2, 3]);
+/// # });
+/// ```
+#[must_use = "streams do nothing unless polled"]
+pub enum SharedHandle<S: Stream> {
+    /// A handle that can't be sent across threads.
+    Local(Shared<S>),
+    /// A handle that can be sent across threads.
+    ThreadSafe(Ashared<S>),
+}
+
+/// Forwards to the handle, so like it this doesn't require [`Debug`](fmt::Debug) for the
+/// underlying stream or its items.
+impl<S: Stream> fmt::Debug for SharedHandle<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(shared) => f.debug_tuple("Local").field(shared).finish(),
+            Self::ThreadSafe(shared) => f.debug_tuple("ThreadSafe").field(shared).finish(),
+        }
+    }
+}
+
+impl<S: Stream> Clone for SharedHandle<S> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Local(shared) => Self::Local(shared.clone()),
+            Self::ThreadSafe(shared) => Self::ThreadSafe(shared.clone()),
+        }
+    }
+}
+
+impl<S: Stream> From<Shared<S>> for SharedHandle<S> {
+    fn from(shared: Shared<S>) -> Self {
+        Self::Local(shared)
+    }
+}
+
+impl<S: Stream> From<Ashared<S>> for SharedHandle<S> {
+    fn from(shared: Ashared<S>) -> Self {
+        Self::ThreadSafe(shared)
+    }
+}
+
+impl<S: Stream> Stream for SharedHandle<S>
+where
+    S::Item: Clone,
+{
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            Self::Local(shared) => shared.poll_next_unpin(cx),
+            Self::ThreadSafe(shared) => shared.poll_next_unpin(cx),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Self::Local(shared) => shared.size_hint(),
+            Self::ThreadSafe(shared) => shared.size_hint(),
+        }
+    }
+}
+
+impl<S: Stream> FusedStream for SharedHandle<S>
+where
+    S::Item: Clone,
+{
+    fn is_terminated(&self) -> bool {
+        match self {
+            Self::Local(shared) => shared.is_terminated(),
+            Self::ThreadSafe(shared) => shared.is_terminated(),
+        }
+    }
+}
+
+/// Picks which [`SharedHandle`] variant [`shared_with_mode`](crate::Share::shared_with_mode)
+/// creates. Implemented by [`Local`] for all streams and by [`ThreadSafe`] for `Send` streams.
+pub trait ShareMode<S: Stream> {
+    /// Turns `stream` into a cloneable stream.
+    fn share(stream: S) -> SharedHandle<S>;
+}
+
+/// [`ShareMode`] creating [`Shared`] handles.
+#[derive(Clone, Copy, Debug)]
+pub struct Local;
+
+/// [`ShareMode`] creating [`Ashared`] handles.
+#[derive(Clone, Copy, Debug)]
+pub struct ThreadSafe;
+
+impl<S: Stream> ShareMode<S> for Local
+where
+    S::Item: Clone,
+{
+    fn share(stream: S) -> SharedHandle<S> {
+        Shared::new(stream).into()
+    }
+}
+
+impl<S: Stream + Send> ShareMode<S> for ThreadSafe
+where
+    S::Item: Clone,
+{
+    fn share(stream: S) -> SharedHandle<S> {
+        Ashared::new(stream).into()
+    }
+}
@@ -0,0 +1,82 @@
+//! Reading shared streams of byte chunks through [`AsyncRead`] and [`AsyncBufRead`].
+
+use crate::storage::Storage;
+use crate::SharedStream;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::{ready, Stream};
+use futures_io::{AsyncBufRead, AsyncRead};
+use futures_util::stream::StreamExt;
+use std::io;
+
+impl<S: Stream, M: Storage<S>> SharedStream<S, M>
+where
+    S::Item: AsRef<[u8]>,
+{
+    /// Turns this handle into a reader of the bytes of the items it yields, concatenated.
+    ///
+    /// Every handle reads all bytes from its own position on, so readers created from clones
+    /// read the same bytes independently of each other. Reads may end in the middle of an item,
+    /// in which case the next read continues with the rest of it.
+    pub const fn into_async_read(self) -> IntoAsyncRead<Self> {
+        IntoAsyncRead {
+            handle: self,
+            chunk: None,
+            pos: 0,
+        }
+    }
+}
+
+/// Reader for the [`into_async_read`](SharedStream::into_async_read) method.
+#[derive(Debug)]
+pub struct IntoAsyncRead<H: Stream> {
+    handle: H,
+    // item that is being read, with the number of its bytes that have been read
+    chunk: Option<H::Item>,
+    pos: usize,
+}
+
+impl<H> AsyncBufRead for IntoAsyncRead<H>
+where
+    H: Stream + Unpin,
+    H::Item: AsRef<[u8]> + Unpin,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        while this
+            .chunk
+            .as_ref()
+            .is_none_or(|chunk| this.pos == chunk.as_ref().len())
+        {
+            this.pos = 0;
+            this.chunk = ready!(this.handle.poll_next_unpin(cx));
+            if this.chunk.is_none() {
+                return Poll::Ready(Ok(&[]));
+            }
+        }
+        let chunk = this.chunk.as_ref().map_or(&[][..], AsRef::as_ref);
+        Poll::Ready(Ok(&chunk[this.pos..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        self.get_mut().pos += amt;
+    }
+}
+
+impl<H> AsyncRead for IntoAsyncRead<H>
+where
+    H: Stream + Unpin,
+    H::Item: AsRef<[u8]> + Unpin,
+{
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
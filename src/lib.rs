@@ -34,6 +34,23 @@
 //!     assert_eq!(shared3.collect::<Vec<_>>().await, [1, 2, 3]);
 //! })).join().unwrap();
 //! ```
+//!
+//! # Features
+//!
+//! * `std` (enabled by default): Back [`Ashared`] with `std::sync::RwLock`.
+//! * `spin`: Back [`Ashared`] with a spin lock if `std` is disabled, which makes it available in
+//!   `no_std` environments (`alloc` is still required). Waiting for the lock busy-waits, so this is only
+//!   advisable where contention is low, e.g. single-core executors or WASM.
+//!
+//! One of these features has to be enabled.
+//!
+//! * `sink`: Implement `Sink` for shared streams whose underlying stream is a sink as well, e.g.
+//!   the read half of a duplex channel, forwarding to it.
+//! * `io`: Read shared streams of byte chunks through `AsyncRead` and `AsyncBufRead` with
+//!   `SharedStream::into_async_read`. Implies `std`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+// some of these lints have since been removed from rustc
+#![allow(renamed_and_removed_lints)]
 #![warn(
     clippy::pedantic,
     clippy::nursery,
@@ -72,375 +89,2049 @@
     variant_size_differences
 )]
 
+#[cfg(not(any(feature = "std", feature = "spin")))]
+compile_error!("shared_stream requires either the `std` or the `spin` feature");
+
+extern crate alloc;
+
+mod adapters;
+mod channel;
+mod growth;
+mod handle;
+#[cfg(feature = "io")]
+mod io;
+mod lock;
+#[cfg(feature = "sink")]
+mod sink;
+mod state;
+mod storage;
+
+#[cfg(feature = "std")]
+pub use crate::adapters::ThreadBound;
+pub use crate::adapters::{
+    Coalesce, Dedup, DedupByKey, Filter, Intersperse, Lazy, OnDrop, Scan, SplitErr, SplitOk, Take,
+    TakeWhile, UntilErr,
+};
+pub use crate::channel::{shared_channel, SharedSender};
+use crate::growth::Growth;
+pub use crate::growth::GrowthPolicy;
+pub use crate::handle::{Local, ShareMode, SharedHandle, ThreadSafe};
+#[cfg(feature = "io")]
+pub use crate::io::IntoAsyncRead;
+#[cfg(feature = "sink")]
+pub use crate::sink::SinkError;
+use crate::state::{Cloner, Fairness, InnerState, Polling, Position};
+use crate::storage::Storage;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::sync::Arc;
+use alloc::vec::{self, Vec};
+use core::cell::RefCell;
+use core::fmt;
+use core::future::Future;
+use core::hash::{Hash, Hasher};
 use core::pin::Pin;
+use core::ptr;
 use core::task::Context;
 use core::task::Poll;
-use futures_core::{FusedStream, Stream};
-use futures_util::task::{waker_ref, ArcWake};
-use pin_project_lite::pin_project;
-use std::cell::RefCell;
-use std::fmt;
-use std::mem;
-use std::rc::Rc;
-use std::sync::Mutex;
-use std::sync::{Arc, RwLock};
-use std::task::Waker;
+use futures_core::{ready, FusedStream, Stream};
+use futures_util::future;
+use futures_util::stream::{self, Empty, Enumerate, LocalBoxStream, Map, StreamExt, Zip};
 
-#[derive(Debug)]
-struct SharedWaker(Mutex<Vec<Waker>>);
+/// Stream for the [`shared`](Share::shared) method.
+pub type Shared<S> = SharedStream<S, Local>;
 
-impl SharedWaker {
-    fn add_waker(&self, cx: &mut Context<'_>) {
-        self.0.lock().unwrap().push(cx.waker().clone());
-    }
+/// Stream for the [`ashared`](Share::ashared) method.
+pub type Ashared<S> = SharedStream<S, ThreadSafe>;
+
+/// Handle to a shared stream, generic over how the state shared by its handles is stored.
+///
+/// This is what [`Shared`] and [`Ashared`] stand for: [`Local`] stores the state in an
+/// `Rc<RefCell<_>>`, while [`ThreadSafe`] stores it in an `Arc<RwLock<_>>`. Prefer those aliases
+/// over naming this type.
+#[must_use = "streams do nothing unless polled"]
+pub struct SharedStream<S: Stream, M: Storage<S>> {
+    inner: M::Ptr,
+    idx: usize,
+    generation: usize,
+    // `idx` as seen by other handles, if the shared stream tracks positions
+    position: Option<Arc<Position>>,
 }
 
-impl ArcWake for SharedWaker {
-    fn wake_by_ref(arc_self: &Arc<Self>) {
-        for waker in mem::take(&mut *arc_self.0.lock().unwrap()) {
-            waker.wake();
+/// Only shows what is known without formatting the underlying stream or its items, so it doesn't
+/// require them to implement [`Debug`](fmt::Debug). See
+/// [`debug_state`](SharedStream::debug_state) for the full state.
+impl<S: Stream, M: Storage<S>> fmt::Debug for SharedStream<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut d = f.debug_struct(M::NAME);
+        let _ = d.field("idx", &self.idx);
+        // the state is borrowed for writing while the underlying stream is polled, which might
+        // format this handle
+        match M::try_read(&self.inner) {
+            Some(inner) => d
+                .field("buffered", &inner.values.len())
+                .field("finished", &inner.stream.is_none())
+                .finish_non_exhaustive(),
+            None => d.finish_non_exhaustive(),
         }
     }
 }
 
-pin_project! {
-    #[project = InnerStateProj]
-    #[derive(Debug)]
-    struct InnerState<S: Stream> {
-        values: Vec<S::Item>,
-        #[pin]
-        stream: Option<S>,
-        waker: Arc<SharedWaker>,
-    }
-}
+/// Value returned by [`debug_state`](SharedStream::debug_state).
+struct DebugState<'a, S: Stream, M: Storage<S>>(&'a SharedStream<S, M>);
 
-impl<S: Stream> InnerState<S> {
-    fn new(stream: S) -> Self {
-        Self {
-            stream: Some(stream),
-            values: vec![],
-            waker: Arc::new(SharedWaker(Mutex::new(vec![]))),
-        }
+impl<S, M> fmt::Debug for DebugState<'_, S, M>
+where
+    S: Stream + fmt::Debug,
+    S::Item: fmt::Debug,
+    M: Storage<S>,
+    M::Ptr: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct(M::NAME)
+            .field("inner", &self.0.inner)
+            .field("idx", &self.0.idx)
+            .field("generation", &self.0.generation)
+            .finish_non_exhaustive()
     }
 }
 
-impl<S: Stream> InnerState<S>
+// Handles only hold a pointer to the state and their position. The state itself, including the
+// underlying stream, is pinned behind the pointer and never moved by moving a handle, so handles
+// are `Unpin` even if the underlying stream isn't, and can be polled with `StreamExt::next`.
+impl<S: Stream, M: Storage<S>> Unpin for SharedStream<S, M> {}
+
+impl<S: Stream> Shared<S>
 where
     S::Item: Clone,
 {
-    fn stream_is_pending(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> bool {
-        let this = self.as_mut().project();
-        let stream = this.stream.as_pin_mut().unwrap();
-        let waker = waker_ref(this.waker);
-        let mut up_cx = Context::from_waker(&waker);
-        match stream.poll_next(&mut up_cx) {
-            Poll::Ready(Some(v)) => {
-                this.values.push(v);
-            }
-            Poll::Ready(None) => {
-                self.as_mut().project().stream.set(None);
-            }
-            Poll::Pending => {
-                this.waker.add_waker(cx);
-                return true;
-            }
-        }
-        false
-    }
-
-    fn known_value(&self, idx: usize) -> Option<Option<S::Item>> {
-        let value = self.values.get(idx).cloned();
-        if value.is_some() || self.stream.is_none() {
-            return Some(value);
-        }
-        None
-    }
-
-    fn size_hint(&self, offset: usize) -> (usize, Option<usize>) {
-        let upstream_cached = self.values.len() - offset;
-        self.stream
-            .as_ref()
-            .map_or((upstream_cached, Some(upstream_cached)), |stream| {
-                let upstream = stream.size_hint();
-                (
-                    upstream.0 + upstream_cached,
-                    upstream.1.map(|v| v + upstream_cached),
-                )
-            })
+    pub(crate) fn new(stream: S) -> Self {
+        Self::from_inner(InnerState::new(stream))
     }
+}
 
-    fn is_terminated(&self, offset: usize) -> bool {
-        self.stream.is_none() && self.values.len() <= offset
+impl<S: Stream + Send> Ashared<S>
+where
+    S::Item: Clone,
+{
+    pub(crate) fn new(stream: S) -> Self {
+        Self::from_inner(InnerState::new(stream))
     }
 }
 
-/// Stream for the [`shared`](Share::shared) method.
-#[must_use = "streams do nothing unless polled"]
-pub struct Shared<S: Stream> {
-    inner: Rc<RefCell<InnerState<S>>>,
-    idx: usize,
+impl<S: Stream> Shared<S> {
+    /// Registers a callback that is invoked with every item the underlying stream produces from
+    /// now on.
+    ///
+    /// Each callback is called exactly once per item, no matter how many handles yield it.
+    /// Callbacks are called in the order they were registered and live as long as the shared
+    /// stream.
+    ///
+    /// Callbacks are called while the underlying stream is being polled, with the shared state
+    /// borrowed. Using a handle of the same shared stream from a callback, e.g. to clone it or
+    /// ask for its buffered items, panics.
+    pub fn add_item_observer(&self, f: impl FnMut(&S::Item) + 'static) {
+        self.inner.borrow_mut().observers.0.push(Box::new(f));
+    }
 }
 
-impl<S> fmt::Debug for Shared<S>
-where
-    S: Stream + fmt::Debug,
-    S::Item: fmt::Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Shared")
-            .field("inner", &self.inner)
-            .field("idx", &self.idx)
-            .finish()
+impl<S: Stream> Ashared<S> {
+    /// Like [`Shared::add_item_observer`], but the callback has to be `Send` and `Sync`, since it
+    /// is called by whichever thread polls the underlying stream.
+    ///
+    /// Callbacks are called with the shared state locked, so using a handle of the same shared
+    /// stream from a callback deadlocks.
+    pub fn add_item_observer(&self, f: impl FnMut(&S::Item) + Send + Sync + 'static) {
+        self.inner.write().observers.0.push(Box::new(f));
     }
 }
 
-impl<S: Stream> Shared<S> {
-    pub(crate) fn new(stream: S) -> Self {
+impl<S: Stream, M: Storage<S>> SharedStream<S, M> {
+    fn from_inner(mut inner: InnerState<S, M>) -> Self {
+        let position = inner.register(0);
         Self {
-            inner: Rc::new(RefCell::new(InnerState::new(stream))),
+            inner: M::new(inner),
             idx: 0,
+            generation: 0,
+            position,
         }
     }
-}
 
-impl<S: Stream> Clone for Shared<S> {
-    fn clone(&self) -> Self {
-        Self {
-            inner: Rc::clone(&self.inner),
-            idx: self.idx,
+    /// Returns all items produced by the underlying stream, including the ones this handle has
+    /// already yielded, without cloning them.
+    ///
+    /// This only succeeds if this is the last handle to the shared stream and the underlying
+    /// stream has finished. Otherwise, `self` is handed back unchanged. Items that have been
+    /// reclaimed (see [`shared_reclaiming`](Share::shared_reclaiming)) aren't included.
+    ///
+    /// # Errors
+    ///
+    /// Returns `self` if there are other handles or the underlying stream hasn't finished yet.
+    pub fn into_vec(self) -> Result<Vec<S::Item>, Self> {
+        if M::read(&self.inner).stream.is_some() {
+            return Err(self);
         }
+        let Self {
+            inner,
+            idx,
+            generation,
+            position,
+        } = self;
+        // moving the state out of its pin is fine because the stream, the only pinned field, has
+        // already been dropped
+        M::try_unwrap(inner)
+            .map(|inner| inner.values)
+            .map_err(|inner| Self {
+                inner,
+                idx,
+                generation,
+                position,
+            })
     }
-}
 
-impl<S: Stream> Stream for Shared<S>
-where
-    S::Item: Clone,
-{
-    type Item = S::Item;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let result = loop {
-            if let Some(v) = self.as_ref().inner.borrow().known_value(self.idx) {
-                break Poll::Ready(v);
-            }
-            // pin project Pin<&mut Self> -> Pin<&mut InnerState<S>>
-            // this is only safe because we don't do anything else with Self::inner except
-            // cloning (the Rc) which doesn't move its content or make it accessible.
-            unsafe {
-                let tmp = self.as_ref();
-                let mut inner = tmp.inner.borrow_mut();
-                if Pin::new_unchecked(&mut *inner).stream_is_pending(cx) {
-                    break Poll::Pending;
-                }
-            }
-        };
-        if let Poll::Ready(Some(_)) = result {
-            // trivial safe pin projection
-            unsafe { Pin::get_unchecked_mut(self).idx += 1 }
-        }
-        result
+    /// Returns an iterator over the items this handle hasn't yielded yet, moving them out of the
+    /// buffer instead of cloning them.
+    ///
+    /// Like [`into_vec`](Self::into_vec), this only succeeds if this is the last handle to the
+    /// shared stream and the underlying stream has finished, so a completed shared stream can be
+    /// drained without an executor.
+    ///
+    /// # Errors
+    ///
+    /// Returns `self` if there are other handles or the underlying stream hasn't finished yet.
+    pub fn try_into_iter(self) -> Result<vec::IntoIter<S::Item>, Self> {
+        let inner = M::read(&self.inner);
+        let skip = inner.cursor(self.generation, self.idx) - inner.reclaimed;
+        drop(inner);
+        self.into_vec().map(|mut values| {
+            drop(values.drain(..skip.min(values.len())));
+            values.into_iter()
+        })
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.borrow().size_hint(self.idx)
+    /// Returns an estimate of the number of bytes held by the shared buffer.
+    ///
+    /// This counts the inline size of the buffered items, including spare capacity, and of the
+    /// shared state. Memory the items own on the heap, like the contents of a `String`, is not
+    /// included; use [`estimated_memory_with`](Self::estimated_memory_with) for that.
+    #[must_use]
+    pub fn estimated_memory(&self) -> usize {
+        M::read(&self.inner).estimated_memory()
     }
-}
 
-impl<S: Stream> FusedStream for Shared<S>
-where
-    S::Item: Clone,
-{
-    fn is_terminated(&self) -> bool {
-        self.inner.borrow().is_terminated(self.idx)
+    /// Like [`estimated_memory`](Self::estimated_memory), but adds `f(item)` for every buffered
+    /// item to account for memory the items own.
+    pub fn estimated_memory_with(&self, f: impl Fn(&S::Item) -> usize) -> usize {
+        M::read(&self.inner).estimated_memory_with(f)
     }
-}
 
-/// Stream for the [`ashared`](Share::ashared) method.
-#[must_use = "streams do nothing unless polled"]
-pub struct Ashared<S: Stream> {
-    inner: Arc<RwLock<InnerState<S>>>,
-    idx: usize,
-}
+    /// Clears the buffer and moves every handle of this shared stream back to the start.
+    ///
+    /// Handles then yield the items the underlying stream produces from now on. This is mostly
+    /// useful together with [`replace_source`](Self::replace_source), so that all handles replay a
+    /// fresh stream from its beginning. Handles waiting for an item are woken.
+    pub fn reset_all(&self) {
+        M::write(&self.inner).reset();
+    }
 
-impl<S> fmt::Debug for Ashared<S>
-where
-    S: Stream + fmt::Debug,
-    S::Item: fmt::Debug,
-{
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Ashared")
-            .field("inner", &self.inner)
-            .field("idx", &self.idx)
-            .finish()
+    /// Returns the positions of all handles of this shared stream, in no particular order.
+    ///
+    /// A position is the index of the next item a handle yields, counted from the first item the
+    /// underlying stream produced. Handles created by adapters like [`windows`](Self::windows)
+    /// are included. This is a snapshot: handles polled concurrently may have moved on by the
+    /// time it is returned.
+    ///
+    /// Handles only register their positions if the shared stream has been created with
+    /// [`shared_tracking`](Share::shared_tracking) or a variant that needs them, like
+    /// [`shared_reclaiming`](Share::shared_reclaiming),
+    /// [`shared_with_capacity`](Share::shared_with_capacity),
+    /// [`ashared_fair`](Share::ashared_fair) or
+    /// [`ashared_max_subscribers`](Share::ashared_max_subscribers). Otherwise, this returns an
+    /// empty `Vec`.
+    #[must_use]
+    pub fn consumer_positions(&self) -> Vec<usize> {
+        M::read(&self.inner).positions()
     }
-}
 
-impl<S: Stream + Send> Ashared<S> {
-    pub(crate) fn new(stream: S) -> Self {
-        Self {
-            inner: Arc::new(RwLock::new(InnerState::new(stream))),
-            idx: 0,
-        }
+    /// Returns the number of live handles of this shared stream, including this one.
+    ///
+    /// Handles wrapped in adapters like [`windows`](Self::windows) are counted as well. Once
+    /// this returns 1, dropping this handle drops the underlying stream and the buffer.
+    #[must_use]
+    pub fn clone_count(&self) -> usize {
+        M::strong_count(&self.inner)
     }
-}
 
-impl<S: Stream> Clone for Ashared<S> {
-    fn clone(&self) -> Self {
-        Self {
-            inner: Arc::clone(&self.inner),
+    /// Changes the number of items the fastest handle may get ahead of the slowest one, which is
+    /// set by [`shared_with_capacity`](Share::shared_with_capacity), for all handles.
+    ///
+    /// Raising the capacity wakes handles waiting for the slowest one, so they continue right
+    /// away if the new capacity allows it. Lowering it drops the items every handle has already
+    /// yielded, but never unread ones: if the slowest handle lags further behind than the new
+    /// capacity, handles wait until it has caught up, as if the buffer had filled up.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero or the shared stream wasn't created with a capacity.
+    pub fn set_capacity(&self, cap: usize) {
+        M::write(&self.inner).set_capacity(cap);
+    }
+
+    /// Returns whether this is the only handle of this shared stream, i.e. whether
+    /// [`clone_count`](Self::clone_count) is 1.
+    ///
+    /// Weak handles (see [`downgrade`](Self::downgrade)) don't count. With [`Ashared`], this is
+    /// only a snapshot: other threads may clone or upgrade handles right after it returns
+    /// `true`, so use [`into_inner`](Self::into_inner) or [`into_vec`](Self::into_vec) to take
+    /// the state out atomically.
+    #[must_use]
+    pub fn is_sole_owner(&self) -> bool {
+        self.clone_count() == 1
+    }
+
+    /// Creates a weak handle at the position of this handle, which doesn't keep the shared
+    /// stream alive.
+    ///
+    /// Once all handles are dropped, so are the underlying stream and the buffer, even if weak
+    /// handles remain. Weak handles aren't counted by [`clone_count`](Self::clone_count).
+    pub fn downgrade(&self) -> WeakSharedStream<S, M> {
+        WeakSharedStream {
+            inner: M::downgrade(&self.inner),
             idx: self.idx,
+            generation: self.generation,
         }
     }
-}
 
-impl<S: Stream> Stream for Ashared<S>
-where
-    S::Item: Clone,
-{
-    type Item = S::Item;
-    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        let result = loop {
-            if let Some(v) = self.as_ref().inner.read().unwrap().known_value(self.idx) {
-                break Poll::Ready(v);
-            }
-            // pin project Pin<&mut Self> -> Pin<&mut InnerState<S>>
-            // this is only safe because we don't do anything else with Self::inner except
-            // cloning (the Arc) which doesn't move its content or make it accessible.
-            unsafe {
-                let tmp = self.as_ref();
-                let mut inner = tmp.inner.write().unwrap();
-                if Pin::new_unchecked(&mut *inner).stream_is_pending(cx) {
-                    break Poll::Pending;
-                }
-            }
-        };
-        if let Poll::Ready(Some(_)) = result {
-            // trivial safe pin projection
-            unsafe { Pin::get_unchecked_mut(self).idx += 1 }
-        }
-        result
+    /// Returns the number of buffered items, regardless of the position of this handle.
+    ///
+    /// In contrast to [`size_hint`](Stream::size_hint), which counts the items this handle
+    /// hasn't yielded yet, this is the number of items kept in memory. Once the underlying
+    /// stream has finished, this is the total number of items, minus the ones that have been
+    /// dropped.
+    #[must_use]
+    pub fn buffered_len(&self) -> usize {
+        M::read(&self.inner).values.len()
     }
 
-    fn size_hint(&self) -> (usize, Option<usize>) {
-        self.inner.read().unwrap().size_hint(self.idx)
+    /// Returns whether no items are buffered. See [`buffered_len`](Self::buffered_len).
+    #[must_use]
+    pub fn buffered_is_empty(&self) -> bool {
+        M::read(&self.inner).values.is_empty()
     }
-}
 
-impl<S: Stream> FusedStream for Ashared<S>
-where
-    S::Item: Clone,
-{
-    fn is_terminated(&self) -> bool {
-        self.inner.read().unwrap().is_terminated(self.idx)
+    /// Returns whether the underlying stream has finished, regardless of the position of this
+    /// handle.
+    ///
+    /// In contrast to [`is_terminated`](FusedStream::is_terminated), this is `true` as soon as the
+    /// underlying stream has returned `None`, even if this handle still has buffered items left
+    /// to yield.
+    #[must_use]
+    pub fn upstream_finished(&self) -> bool {
+        M::read(&self.inner).stream.is_none()
     }
-}
 
-/// An extension trait implemented for [`Stream`]s that provides the [`shared`](Share::shared) and [`ashared`](Share::ashared) methods.
-pub trait Share: Stream {
-    /// Turns this stream into a cloneable stream. Polled items are cached and cloned.
+    /// Returns the number of items the underlying stream has produced so far.
     ///
-    /// Note that this function consumes the stream passed into it and returns a wrapped version of it.
-    fn shared(self) -> Shared<Self>
-    where
-        Self: Sized,
-        Self::Item: Clone;
+    /// In contrast to [`buffered_len`](Self::buffered_len), this includes items that have been
+    /// dropped from the buffer, including by [`reset_all`](Self::reset_all), so it never
+    /// decreases.
+    #[must_use]
+    pub fn items_produced(&self) -> usize {
+        M::read(&self.inner).produced
+    }
 
-    /// Turns this stream into a cloneable stream that can be shared across threads. Polled items are cached and cloned.
+    /// Returns the index of the item this handle yields next, counted from the first item the
+    /// underlying stream produced.
     ///
-    /// Note that this function consumes the stream passed into it and returns a wrapped version of it.
-    fn ashared(self) -> Ashared<Self>
-    where
-        Self: Sized + Send,
-        Self::Item: Clone;
-}
+    /// This accounts for resets and dropped items the handle hasn't noticed yet, since it does
+    /// so once it is polled. [`size_hint`](Stream::size_hint) counts the items from this position
+    /// on.
+    ///
+    /// Positions don't wrap around: the underlying stream producing an item whose successor's
+    /// position doesn't fit in a [`usize`] panics, even with dropped items, which are still
+    /// counted. On 64-bit targets, that takes centuries even at a billion items per second.
+    #[must_use]
+    pub fn position(&self) -> usize {
+        M::read(&self.inner).cursor(self.generation, self.idx)
+    }
 
-impl<T: Stream> Share for T
-where
-    T::Item: Clone,
-{
-    fn shared(self) -> Shared<Self> {
-        Shared::new(self)
+    /// Moves this handle to the item at `idx`, counted from the first item the underlying stream
+    /// produced, so that it yields that item next.
+    ///
+    /// Seeking backwards replays items as long as they are still buffered. A handle seeking to an
+    /// item that has been dropped (see [`shared_reclaiming`](Share::shared_reclaiming)) continues
+    /// with the first buffered item instead. Seeking forwards past the buffered items polls the
+    /// underlying stream for the skipped items once this handle is polled, and seeking past the
+    /// end of a finished stream makes this handle yield `None`.
+    pub fn seek(&mut self, idx: usize) {
+        self.idx = idx;
+        self.generation = M::read(&self.inner).generation;
+        self.store_position(idx);
     }
 
-    fn ashared(self) -> Ashared<Self>
+    /// Moves this handle back to the first item, so it yields every buffered item again.
+    ///
+    /// This is the same as `seek(0)`. With variants that drop items, like
+    /// [`shared_reclaiming`](Share::shared_reclaiming), the handle continues with the first
+    /// item that is still buffered instead.
+    pub fn rewind(&mut self) {
+        self.seek(0);
+    }
+
+    /// Returns a value whose [`Debug`](fmt::Debug) output includes the whole shared state, i.e.
+    /// the underlying stream and the buffered items, unlike the output of this handle itself.
+    pub fn debug_state(&self) -> impl fmt::Debug + '_
     where
-        T: Send,
+        S: fmt::Debug,
+        S::Item: fmt::Debug,
+        M::Ptr: fmt::Debug,
     {
-        Ashared::new(self)
+        DebugState(self)
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::Share;
-    use core::cell::RefCell;
-    use futures::executor::block_on;
-    use futures::future;
-    use futures::stream::{self, StreamExt};
-    use futures_core::stream::{FusedStream, Stream};
-    use std::sync::RwLock;
+    /// Creates a new handle that yields the item at `idx` next, counted from the first item the
+    /// underlying stream produced.
+    ///
+    /// This is like [`clone`](Clone::clone) followed by [`seek`](Self::seek), without the new
+    /// handle ever holding on to the position of this one. If `idx` is past the buffered items,
+    /// the new handle polls the underlying stream for the skipped items once it is polled.
+    pub fn clone_at(&self, idx: usize) -> Self {
+        let mut inner = M::write(&self.inner);
+        let generation = inner.generation;
+        let cursor = inner.cursor(generation, idx);
+        let position = inner.register(cursor);
+        drop(inner);
+        Self {
+            inner: self.inner.clone(),
+            idx,
+            generation,
+            position,
+        }
+    }
 
-    fn collect<V: Clone, S: Stream<Item = V>>(stream: S) -> Vec<V> {
-        block_on(stream.collect::<Vec<_>>())
+    /// Returns the underlying stream, so it can be polled without the overhead of buffering.
+    ///
+    /// This only succeeds if this is the last handle to the shared stream, the underlying stream
+    /// hasn't finished and this handle has yielded every buffered item, so no item is lost.
+    /// Otherwise, `self` is handed back unchanged. The underlying stream has to be [`Unpin`]
+    /// since it is pinned inside the shared state.
+    ///
+    /// # Errors
+    ///
+    /// Returns `self` if there are other handles, the underlying stream has finished or there
+    /// are buffered items this handle hasn't yielded yet.
+    pub fn into_inner(self) -> Result<S, Self>
+    where
+        S: Unpin,
+    {
+        let inner = M::read(&self.inner);
+        let caught_up =
+            inner.cursor(self.generation, self.idx) == inner.reclaimed + inner.values.len();
+        drop(inner);
+        if M::strong_count(&self.inner) > 1 || !caught_up {
+            return Err(self);
+        }
+        let stream = M::write(&self.inner).stream.take();
+        stream.ok_or(self)
     }
 
-    fn test_everything<
-        S: Clone + Stream<Item = String> + FusedStream + Unpin,
-        F: Fn() -> Vec<String>,
-    >(
-        orig_stream: S,
-        seen: F,
-    ) {
-        assert_eq!(seen(), [] as [String; 0]);
-        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
-        assert!(!orig_stream.is_terminated());
+    /// Creates a handle that only yields items produced from now on, skipping the items that
+    /// are already buffered.
+    ///
+    /// In contrast, [`clone`](Clone::clone) creates a handle at the position of this one. If the
+    /// underlying stream has already finished, the new handle doesn't yield anything.
+    pub fn subscribe_live(&self) -> Self {
+        let mut inner = M::write(&self.inner);
+        let idx = inner.reclaimed + inner.values.len();
+        let generation = inner.generation;
+        let position = inner.register(idx);
+        drop(inner);
+        Self {
+            inner: self.inner.clone(),
+            idx,
+            generation,
+            position,
+        }
+    }
 
-        let stream = orig_stream.clone().take(1);
-        assert_eq!(stream.size_hint(), (1, Some(1)));
-        assert!(!stream.is_terminated());
-        let result = collect(stream);
-        assert_eq!(result, ["a"]);
-        assert_eq!(seen(), ["a"]);
-        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
-        assert!(!orig_stream.is_terminated());
+    /// Creates a handle at the position of this one, which yields the same items as this one.
+    ///
+    /// This is the same as [`clone`](Clone::clone), spelled out for symmetry with
+    /// [`fork_from_start`](Self::fork_from_start) and [`subscribe_live`](Self::subscribe_live).
+    pub fn fork_here(&self) -> Self {
+        self.clone()
+    }
 
-        let stream = orig_stream.clone();
-        assert_eq!(stream.size_hint(), (3, Some(3)));
-        assert!(!stream.is_terminated());
-        let result = collect(stream);
-        assert_eq!(result, ["a", "b", "c"]);
-        assert_eq!(seen(), ["a", "b", "c"]);
-        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
-        assert!(!orig_stream.is_terminated());
+    /// Creates a handle at the first item, which yields every buffered item, regardless of the
+    /// position of this handle.
+    ///
+    /// This is the same as `clone_at(0)`, see [`clone_at`](Self::clone_at). With variants that
+    /// drop items, like [`shared_reclaiming`](Share::shared_reclaiming), the new handle starts
+    /// with the first item that is still buffered.
+    pub fn fork_from_start(&self) -> Self {
+        self.clone_at(0)
+    }
 
-        let stream1 = orig_stream.clone().skip(1);
-        assert_eq!(stream1.size_hint(), (2, Some(2)));
-        assert!(!stream1.is_terminated());
-        let stream2 = orig_stream.clone();
-        assert_eq!(stream2.size_hint(), (3, Some(3)));
-        assert!(!stream2.is_terminated());
-        let (result1, result2): (Vec<_>, Vec<_>) =
-            block_on(future::join(stream1.collect(), stream2.collect()));
-        assert_eq!(result1, ["b", "c"]);
-        assert_eq!(result2, ["a", "b", "c"]);
-        assert_eq!(seen(), ["a", "b", "c"]);
-        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
-        assert!(!orig_stream.is_terminated());
+    /// Frees the spare capacity of the buffer once the underlying stream has finished.
+    ///
+    /// A finished buffer never grows again, so the capacity left over from growing it while the
+    /// underlying stream was running is wasted. This already happens automatically when the
+    /// underlying stream finishes, so calling this is rarely needed. This does nothing while the
+    /// underlying stream is still running.
+    pub fn compact_finished(&self) {
+        M::write(&self.inner).compact_finished();
+    }
 
-        let mut stream1 = orig_stream.clone();
-        assert_eq!(Some("a".to_string()), block_on(stream1.next()));
-        assert_eq!(stream1.size_hint(), (2, Some(2)));
-        assert!(!stream1.is_terminated());
-        assert_eq!(Some("b".to_string()), block_on(stream1.next()));
-        assert_eq!(stream1.size_hint(), (1, Some(1)));
-        assert!(!stream1.is_terminated());
-        assert_eq!(Some("c".to_string()), block_on(stream1.next()));
-        assert_eq!(stream1.size_hint(), (0, Some(0)));
-        assert!(stream1.is_terminated());
-        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
-        assert!(!orig_stream.is_terminated());
+    /// Polls the underlying stream until it has finished, buffering all of its items, without
+    /// moving any handle.
+    ///
+    /// Returns [`Poll::Pending`] if the underlying stream isn't ready, in which case the task of
+    /// `cx` is woken once it can make progress. Returns [`Poll::Ready`] once the underlying
+    /// stream has finished.
+    pub fn poll_until_finished(&self, cx: &mut Context<'_>) -> Poll<()> {
+        // the state is pinned behind the pointer, see `poll_item`
+        unsafe { Pin::new_unchecked(&mut *M::write(&self.inner)).poll_until_finished(cx) }
     }
 
-    #[test]
+    /// Turns this handle into a future that polls the underlying stream to the end, buffering
+    /// its items as soon as they are produced.
+    ///
+    /// Spawn it on an executor if the underlying stream should make progress even while no
+    /// handle is polled, e.g. for a fast producer with sporadic consumers. Handles waiting for an
+    /// item are woken once it has been produced. The future doesn't yield any items itself, so
+    /// it doesn't hold back other handles of a bounded buffer (see
+    /// [`shared_with_capacity`](Share::shared_with_capacity)) or reclaiming items.
+    // the future is `Send` whenever the handle is
+    #[allow(clippy::future_not_send)]
+    pub fn pump(self) -> impl Future<Output = ()> {
+        // dropping the position unregisters this handle
+        let Self { inner, .. } = self;
+        future::poll_fn(move |cx| {
+            // the state is pinned behind the pointer, see `poll_item`
+            unsafe { Pin::new_unchecked(&mut *M::write(&inner)).poll_until_finished(cx) }
+        })
+    }
+
+    /// Returns a future that polls the underlying stream until it has produced the items before
+    /// `upto`, counted from the first item, or it has finished, without moving any handle.
+    ///
+    /// This warms up the buffer, so handles read those items right away later. The future
+    /// resolves immediately if the items have already been produced.
+    // the future is `Send` whenever the handle is
+    #[allow(clippy::future_not_send)]
+    pub fn prefetch(&self, upto: usize) -> impl Future<Output = ()> {
+        let inner = self.inner.clone();
+        future::poll_fn(move |cx| {
+            // the state is pinned behind the pointer, see `poll_item`
+            unsafe { Pin::new_unchecked(&mut *M::write(&inner)).poll_until(cx, upto) }
+        })
+    }
+
+    /// Returns a future that polls the underlying stream to the end like [`pump`](Self::pump),
+    /// and resolves to the number of items of the shared stream.
+    ///
+    /// The items are buffered for the handles, so they don't poll the underlying stream again,
+    /// and several of these futures share the work: whichever is polled produces items for all
+    /// of them. Items dropped from the buffer, e.g. by
+    /// [`shared_reclaiming`](Share::shared_reclaiming), are counted as well.
+    // the future is `Send` whenever the handle is
+    #[allow(clippy::future_not_send)]
+    pub fn count_future(&self) -> impl Future<Output = usize> {
+        let inner = self.inner.clone();
+        future::poll_fn(move |cx| {
+            let mut state = M::write(&inner);
+            // the state is pinned behind the pointer, see `poll_item`
+            ready!(unsafe { Pin::new_unchecked(&mut *state).poll_until_finished(cx) });
+            Poll::Ready(state.reclaimed + state.values.len())
+        })
+    }
+
+    /// Replaces the underlying stream, dropping the current one.
+    ///
+    /// Items produced by `stream` are buffered after the items already cached, so handles
+    /// continue with them as if the streams were chained. Combine this with
+    /// [`reset_all`](Self::reset_all) to start over instead. Note that this revives handles that
+    /// have already reported [`is_terminated`](FusedStream::is_terminated).
+    pub fn replace_source(&self, stream: S) {
+        // the state is pinned behind the pointer, see `poll_item`
+        unsafe { Pin::new_unchecked(&mut *M::write(&self.inner)).replace_source(stream) }
+    }
+
+    /// Ends the shared stream early by dropping the underlying stream, as if it had finished.
+    ///
+    /// Handles yield the items that are already buffered and end after them. Handles waiting
+    /// for the next item are woken to notice the end. This can't interrupt the underlying stream
+    /// while it is being polled: with [`Ashared`], this waits for the poll to return, and calling
+    /// this from within the underlying stream panics with [`Shared`] and deadlocks with
+    /// [`Ashared`]. Use [`replace_source`](Self::replace_source) to continue with another stream.
+    ///
+    /// This isn't named `close` so it doesn't shadow `SinkExt::close` for shared sinks.
+    pub fn close_source(&self) {
+        // the state is pinned behind the pointer, see `poll_item`
+        unsafe { Pin::new_unchecked(&mut *M::write(&self.inner)).close_source() }
+    }
+
+    /// Turns this handle into a stream of overlapping windows of `n` consecutive items, starting
+    /// at the current position of this handle and advancing by one item per window.
+    ///
+    /// The windows are cloned from the shared buffer, so no extra items are buffered per
+    /// consumer. A window is only yielded once all of its `n` items have been produced; when the
+    /// underlying stream ends, the remaining items that don't fill a whole window are skipped.
+    ///
+    /// With [`shared_with_capacity`](Share::shared_with_capacity), the items of a window count
+    /// against the capacity, since this handle stays at the start of the window until it is
+    /// complete. Lowering the capacity below `n` with [`set_capacity`](Self::set_capacity)
+    /// therefore makes the window wait forever.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0 or greater than the capacity of the shared stream.
+    pub fn windows(self, n: usize) -> Windows<Self> {
+        assert!(n != 0, "window size must be non-zero");
+        assert!(
+            M::read(&self.inner).max_buffered.is_none_or(|cap| n <= cap),
+            "window size must not exceed the capacity"
+        );
+        Windows { handle: self, n }
+    }
+
+    /// Turns this handle into a stream that pairs every item with the lower bound of
+    /// [`size_hint`](Stream::size_hint) right after it has been yielded, i.e. the number of items
+    /// that are known to follow it.
+    ///
+    /// The count is a snapshot: it includes items that are already buffered and items the
+    /// underlying stream reports in its own size hint, and may grow as more items are produced.
+    pub const fn with_remaining(self) -> WithRemaining<Self> {
+        WithRemaining { handle: self }
+    }
+
+    /// Splits this handle into `n` streams that take turns yielding the items from the current
+    /// position of this handle on: the first stream yields the first item, the second stream
+    /// the second one and so on, starting over with the first stream after `n` items.
+    ///
+    /// The items are partitioned among the streams rather than duplicated, so every item is
+    /// yielded by exactly one of them. They are still produced by polling the underlying stream
+    /// once and buffered like for any other handles, so a stream polls the items meant for other
+    /// streams if it gets ahead of them.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is 0.
+    pub fn split_round_robin(self, n: usize) -> Vec<RoundRobin<Self>> {
+        assert!(n != 0, "number of streams must be non-zero");
+        let start = self.position();
+        let mut streams: Vec<_> = (1..n)
+            .map(|i| RoundRobin {
+                handle: self.clone_at(start + i),
+                n,
+            })
+            .collect();
+        streams.insert(0, RoundRobin { handle: self, n });
+        streams
+    }
+
+    /// Turns this handle into a cursor that can be polled through shared references, so that
+    /// several places advance the same position.
+    ///
+    /// In contrast to cloning the handle, every item is only yielded through one of the
+    /// references. Note that the cursor can't be polled from within an item observer (see
+    /// [`add_item_observer`](Self::add_item_observer)).
+    pub const fn into_shared_cursor(self) -> SharedCursor<Self> {
+        SharedCursor {
+            handle: RefCell::new(self),
+        }
+    }
+
+    /// Zips this handle with a handle of another shared stream into a new shared stream of
+    /// pairs, which can be cloned like any other.
+    ///
+    /// The `n`th pair consists of the `n`th item each handle would have yielded from its current
+    /// position on, and the combined stream ends as soon as either of them does. Both handles are
+    /// only polled by the combined stream, so its clones share the pairs instead of driving the
+    /// sources again. Other handles of the two streams aren't affected.
+    pub fn zip_shared<T>(self, other: SharedStream<T, M>) -> ZipShared<S, T, M>
+    where
+        T: Stream,
+        M: Storage<T> + Storage<Zip<Self, SharedStream<T, M>>>,
+        S::Item: Clone,
+        T::Item: Clone,
+    {
+        SharedStream::from_inner(InnerState::new(self.zip(other)))
+    }
+}
+
+/// Creates a handle at the position of this one, which yields the same items as this one from now
+/// on. See [`fork_from_start`](SharedStream::fork_from_start) and
+/// [`subscribe_live`](SharedStream::subscribe_live) for handles starting elsewhere.
+impl<S: Stream, M: Storage<S>> Clone for SharedStream<S, M> {
+    fn clone(&self) -> Self {
+        // without tracked positions, cloning a handle doesn't touch the state
+        let position = if M::read(&self.inner).tracks_positions() {
+            let mut inner = M::write(&self.inner);
+            let idx = inner.cursor(self.generation, self.idx);
+            inner.register(idx)
+        } else {
+            None
+        };
+        Self {
+            inner: self.inner.clone(),
+            idx: self.idx,
+            generation: self.generation,
+            position,
+        }
+    }
+}
+
+impl<S: Stream, M: Storage<S>> SharedStream<S, M> {
+    /// Returns the items this handle hasn't yielded yet as an [`Iterator`], so they can be used
+    /// from synchronous code without an executor.
+    ///
+    /// This consumes the handle and only succeeds once the underlying stream has finished. The
+    /// iterator works on a snapshot of the buffer, so other handles aren't affected by it.
+    #[must_use]
+    pub fn finished_iter(self) -> Option<impl Iterator<Item = S::Item>> {
+        let inner = M::read(&self.inner);
+        inner
+            .finished_items(inner.cursor(self.generation, self.idx))
+            .map(Vec::into_iter)
+    }
+
+    /// Returns the item this handle yields next without advancing it, polling the underlying
+    /// stream if the item hasn't been produced yet.
+    ///
+    /// The item is cloned out of the buffer, since the buffer can't be borrowed from while other
+    /// handles may add to it.
+    pub fn poll_peek(&mut self, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let mut polling = self.enter();
+        self.sync_position();
+        self.poll_item(self.idx, cx, &mut polling)
+    }
+
+    /// Returns up to `max` items this handle yields next at once, cloning all buffered ones under
+    /// a single lock.
+    ///
+    /// This is cheaper than polling item by item for a handle that lags behind. Only if no items
+    /// are buffered, the underlying stream is polled for the next one. An empty chunk is only
+    /// returned once this handle has yielded all items.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `max` is 0.
+    pub fn poll_next_chunk(&mut self, cx: &mut Context<'_>, max: usize) -> Poll<Vec<S::Item>> {
+        assert!(max != 0, "chunk size must be non-zero");
+        let mut polling = self.enter();
+        self.sync_position();
+        let mut chunk = M::read(&self.inner).buffered_items(self.idx, max);
+        if chunk.is_empty() {
+            match self.poll_item(self.idx, cx, &mut polling) {
+                Poll::Ready(Some(v)) => chunk.push(v),
+                Poll::Ready(None) => return Poll::Ready(chunk),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        self.idx += chunk.len();
+        self.store_position(self.idx);
+        Poll::Ready(chunk)
+    }
+
+    /// Takes all items this handle yields next that are already buffered, without polling the
+    /// underlying stream.
+    ///
+    /// Returns an empty `Vec` if no such items are buffered, e.g. because this handle is the one
+    /// furthest ahead.
+    pub fn drain_ready(&mut self) -> Vec<S::Item> {
+        self.sync_position();
+        let items = M::read(&self.inner).buffered_items(self.idx, usize::MAX);
+        if !items.is_empty() {
+            self.idx += items.len();
+            self.store_position(self.idx);
+        }
+        items
+    }
+
+    /// Like [`poll_peek`](Self::poll_peek), but returns a future.
+    // the future is `Send` whenever the handle is
+    #[allow(clippy::future_not_send)]
+    pub fn peek(&mut self) -> impl Future<Output = Option<S::Item>> + '_ {
+        future::poll_fn(|cx| self.poll_peek(cx))
+    }
+
+    /// Moves this handle back to the start if the buffer has been reset since it was last polled,
+    /// or to the first buffered item if the items it would yield next have been dropped.
+    fn sync_position(&mut self) {
+        let inner = M::read(&self.inner);
+        let generation = inner.generation;
+        let idx = inner.cursor(self.generation, self.idx);
+        drop(inner);
+        if generation != self.generation || idx != self.idx {
+            self.idx = idx;
+            self.generation = generation;
+            self.store_position(idx);
+        }
+    }
+
+    fn advance(&mut self) {
+        self.idx += 1;
+        self.store_position(self.idx);
+    }
+
+    fn store_position(&self, idx: usize) {
+        if let Some(position) = &self.position {
+            position.store(idx);
+        }
+    }
+
+    /// Marks this handle as being polled, see [`Position::enter`].
+    fn enter(&self) -> Option<Polling> {
+        self.position.as_ref()?.enter()
+    }
+
+    /// Returns the item at `idx`, polling the underlying stream until it has been produced.
+    ///
+    /// `polling` is the guard returned by [`Position::enter`], which has to be taken before any
+    /// lock, so that other handles know this one is waiting for its turn.
+    fn poll_item(
+        &self,
+        idx: usize,
+        cx: &Context<'_>,
+        polling: &mut Option<Polling>,
+    ) -> Poll<Option<S::Item>> {
+        let mut polled = 0;
+        loop {
+            let known = M::read(&self.inner).known_value(idx);
+            if let Some(v) = known {
+                return Poll::Ready(v);
+            }
+            // the state is pinned behind the pointer, see `poll_locked`
+            let result = unsafe {
+                self.poll_locked(&mut M::write(&self.inner), idx, cx, polling, &mut polled)
+            };
+            if let Some(result) = result {
+                return result;
+            }
+        }
+    }
+
+    /// Polls the underlying stream once for the item at `idx`, with the state locked for
+    /// writing. Returns `None` if an item has been produced that isn't the one at `idx` yet, so
+    /// the caller has to poll again.
+    ///
+    /// `polled` counts the polls of the underlying stream towards the budget (see
+    /// [`shared_with_budget`](Share::shared_with_budget)).
+    ///
+    /// # Safety
+    ///
+    /// `inner` has to be the state behind `self.inner`. It is pinned there, since we don't do
+    /// anything else with `self.inner` except cloning the pointer, which doesn't move its
+    /// content or make it accessible.
+    unsafe fn poll_locked(
+        &self,
+        inner: &mut InnerState<S, M>,
+        idx: usize,
+        cx: &Context<'_>,
+        polling: &mut Option<Polling>,
+        polled: &mut usize,
+    ) -> Option<Poll<Option<S::Item>>> {
+        // another handle may have produced the item, or finished the underlying stream, between
+        // the read lock and the write lock
+        if let Some(v) = inner.known_value(idx) {
+            return Some(Poll::Ready(v));
+        }
+        if inner.budget_exhausted(polled, cx) {
+            return Some(Poll::Pending);
+        }
+        if !inner.take_turn(self.position.as_ref(), cx) {
+            if let Some(polling) = polling {
+                polling.yielded = true;
+            }
+            return Some(Poll::Pending);
+        }
+        // pin project &M::Ptr -> Pin<&mut InnerState<S, M>>, see above
+        if unsafe { Pin::new_unchecked(&mut *inner) }.stream_is_pending(cx) {
+            return Some(Poll::Pending);
+        }
+        // take the item right away if it has just been produced, instead of locking again
+        inner.known_value(idx).map(Poll::Ready)
+    }
+}
+
+impl<S: Stream, M: Storage<S>> Stream for SharedStream<S, M> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut polling = this.enter();
+        this.sync_position();
+        let result = this.poll_item(this.idx, cx, &mut polling);
+        if let Poll::Ready(Some(_)) = result {
+            this.advance();
+        }
+        result
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let inner = M::read(&self.inner);
+        inner.size_hint(inner.cursor(self.generation, self.idx))
+    }
+}
+
+/// Handles are fused even if the underlying stream isn't: the underlying stream is dropped once
+/// it returns `None`, so every handle ends after the items produced until then, and keeps
+/// returning `None` after that.
+impl<S: Stream, M: Storage<S>> FusedStream for SharedStream<S, M> {
+    fn is_terminated(&self) -> bool {
+        let inner = M::read(&self.inner);
+        inner.is_terminated(inner.cursor(self.generation, self.idx))
+    }
+}
+
+/// Handles are equal if they yield the same items from now on, as far as that is known without
+/// polling.
+///
+/// Handles of the same shared stream are equal if they are at the same position. Handles of
+/// different shared streams are only equal if both underlying streams have finished, with the
+/// same items buffered and the handles at the same position, which makes this mostly useful for
+/// comparing finished streams, e.g. in tests.
+impl<S: Stream, M: Storage<S>> PartialEq for SharedStream<S, M>
+where
+    S::Item: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        if M::ptr_eq(&self.inner, &other.inner) {
+            return self.position() == other.position();
+        }
+        let a = M::read(&self.inner);
+        let b = M::read(&other.inner);
+        a.stream.is_none()
+            && b.stream.is_none()
+            && a.reclaimed == b.reclaimed
+            && a.values == b.values
+            && a.cursor(self.generation, self.idx) == b.cursor(other.generation, other.idx)
+    }
+}
+
+/// Consistent with [`PartialEq`]: a handle of a finished shared stream is hashed by the buffered
+/// items and its position, while a handle of a running one is hashed by the identity of the
+/// shared stream and its position.
+///
+/// The hash of a handle therefore changes once the underlying stream finishes, so only handles of
+/// finished streams should be used as keys, e.g. of a cache.
+impl<S: Stream, M: Storage<S>> Hash for SharedStream<S, M>
+where
+    S::Item: Hash,
+{
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        let inner = M::read(&self.inner);
+        if inner.stream.is_some() {
+            ptr::from_ref::<InnerState<S, M>>(&inner).hash(state);
+        } else {
+            inner.reclaimed.hash(state);
+            inner.values.hash(state);
+        }
+        inner.cursor(self.generation, self.idx).hash(state);
+    }
+}
+
+impl<T: Clone, M: Storage<Empty<T>>> SharedStream<Empty<T>, M> {
+    /// Creates a shared stream that has already finished, so every handle yields `items` without
+    /// polling anything.
+    ///
+    /// This is handy for tests and for seeding a shared stream with items that are known up
+    /// front.
+    pub fn from_complete(items: Vec<T>) -> Self {
+        Self::from_inner(InnerState {
+            produced: items.len(),
+            values: items,
+            stream: None,
+            ..InnerState::new(stream::empty())
+        })
+    }
+
+    /// Creates a shared stream that has already finished without yielding any items.
+    pub fn empty() -> Self {
+        Self::from_complete(Vec::new())
+    }
+}
+
+impl<T: Clone, M: Storage<Empty<T>>> Default for SharedStream<Empty<T>, M> {
+    fn default() -> Self {
+        Self::empty()
+    }
+}
+
+impl<T: Clone, M: Storage<Empty<T>>> From<Vec<T>> for SharedStream<Empty<T>, M> {
+    fn from(items: Vec<T>) -> Self {
+        Self::from_complete(items)
+    }
+}
+
+impl<T: Clone, M: Storage<Empty<T>>> FromIterator<T> for SharedStream<Empty<T>, M> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        Self::from_complete(iter.into_iter().collect())
+    }
+}
+
+/// Error returned by [`Ashared::try_poll_next`] if the state of the shared stream is locked by
+/// another thread.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WouldBlock;
+
+impl fmt::Display for WouldBlock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the shared stream is locked by another thread")
+    }
+}
+
+impl core::error::Error for WouldBlock {}
+
+impl<S: Stream> Ashared<S> {
+    /// Like [`poll_next`](Stream::poll_next), but returns `Poll::Ready(Some(Err(WouldBlock)))`
+    /// instead of waiting if another thread holds the lock on the state of the shared stream.
+    ///
+    /// Reading buffered items only needs the lock to be shared, while polling the underlying
+    /// stream needs it exclusively, so this reports contention mostly while another handle is
+    /// polling the underlying stream. The task of `cx` isn't woken once the lock is released,
+    /// so retry later in that case, e.g. after yielding to the executor. The handle doesn't move
+    /// unless an item is returned.
+    pub fn try_poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<S::Item, WouldBlock>>> {
+        let mut polling = self.enter();
+        let Some(inner) = self.inner.try_read() else {
+            return Poll::Ready(Some(Err(WouldBlock)));
+        };
+        let generation = inner.generation;
+        let idx = inner.cursor(self.generation, self.idx);
+        drop(inner);
+        if generation != self.generation || idx != self.idx {
+            self.idx = idx;
+            self.generation = generation;
+            self.store_position(idx);
+        }
+        let mut polled = 0;
+        let item = loop {
+            let Some(inner) = self.inner.try_read() else {
+                return Poll::Ready(Some(Err(WouldBlock)));
+            };
+            if let Some(v) = inner.known_value(idx) {
+                break v;
+            }
+            drop(inner);
+            let Some(mut inner) = self.inner.try_write() else {
+                return Poll::Ready(Some(Err(WouldBlock)));
+            };
+            // the state is pinned behind the pointer, see `poll_locked`
+            match unsafe { self.poll_locked(&mut inner, idx, cx, &mut polling, &mut polled) } {
+                Some(Poll::Ready(v)) => break v,
+                Some(Poll::Pending) => return Poll::Pending,
+                None => {}
+            }
+        };
+        if item.is_some() {
+            self.advance();
+        }
+        Poll::Ready(item.map(Ok))
+    }
+
+    /// Clones this handle, unless there are already as many handles as allowed by
+    /// [`ashared_max_subscribers`](Share::ashared_max_subscribers).
+    ///
+    /// Checking and registering the new handle happens atomically, so the limit holds even when
+    /// handles are cloned on several threads at once. Dropped handles free up their slot. Note
+    /// that [`clone`](Clone::clone) ignores the limit, but its handles count towards it.
+    #[must_use]
+    pub fn try_clone(&self) -> Option<Self> {
+        let mut inner = self.inner.write();
+        let idx = inner.cursor(self.generation, self.idx);
+        let position = inner.try_register(idx)?;
+        drop(inner);
+        Some(Self {
+            inner: Arc::clone(&self.inner),
+            idx: self.idx,
+            generation: self.generation,
+            position,
+        })
+    }
+}
+
+/// Stream for the [`windows`](SharedStream::windows) method.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct Windows<H> {
+    handle: H,
+    n: usize,
+}
+
+impl<H: Stream> Windows<H> {
+    fn windows_size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.handle.size_hint();
+        (
+            lower.saturating_sub(self.n - 1),
+            upper.map(|v| v.saturating_sub(self.n - 1)),
+        )
+    }
+}
+
+impl<S: Stream, M: Storage<S>> Stream for Windows<SharedStream<S, M>> {
+    type Item = Vec<S::Item>;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let mut polling = this.handle.enter();
+        this.handle.sync_position();
+        let mut window = Vec::with_capacity(this.n);
+        for idx in this.handle.idx..this.handle.idx + this.n {
+            match this.handle.poll_item(idx, cx, &mut polling) {
+                Poll::Ready(Some(v)) => window.push(v),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        this.handle.advance();
+        Poll::Ready(Some(window))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.windows_size_hint()
+    }
+}
+
+/// Stream for the [`with_remaining`](SharedStream::with_remaining) method.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct WithRemaining<H> {
+    handle: H,
+}
+
+impl<H: Stream + Unpin> Stream for WithRemaining<H> {
+    type Item = (H::Item, usize);
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.handle
+            .poll_next_unpin(cx)
+            .map(|v| v.map(|v| (v, this.handle.size_hint().0)))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.handle.size_hint()
+    }
+}
+
+impl<H: FusedStream + Unpin> FusedStream for WithRemaining<H> {
+    fn is_terminated(&self) -> bool {
+        self.handle.is_terminated()
+    }
+}
+
+/// Stream for the [`split_round_robin`](SharedStream::split_round_robin) method.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct RoundRobin<H> {
+    handle: H,
+    n: usize,
+}
+
+impl<S: Stream, M: Storage<S>> Stream for RoundRobin<SharedStream<S, M>> {
+    type Item = S::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        let item = ready!(this.handle.poll_next_unpin(cx));
+        if item.is_some() {
+            // skip the items of the other streams
+            let next = this.handle.position() + this.n - 1;
+            this.handle.seek(next);
+        }
+        Poll::Ready(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let (lower, upper) = self.handle.size_hint();
+        (lower.div_ceil(self.n), upper.map(|v| v.div_ceil(self.n)))
+    }
+}
+
+impl<S: Stream, M: Storage<S>> FusedStream for RoundRobin<SharedStream<S, M>> {
+    fn is_terminated(&self) -> bool {
+        self.handle.is_terminated()
+    }
+}
+
+/// Cursor for the [`into_shared_cursor`](SharedStream::into_shared_cursor) method, which is a
+/// stream through shared references.
+#[derive(Debug)]
+#[must_use = "streams do nothing unless polled"]
+pub struct SharedCursor<H> {
+    handle: RefCell<H>,
+}
+
+impl<H: Stream + Unpin> Stream for &SharedCursor<H> {
+    type Item = H::Item;
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.handle.borrow_mut().poll_next_unpin(cx)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.handle.borrow().size_hint()
+    }
+}
+
+impl<H: FusedStream + Unpin> FusedStream for &SharedCursor<H> {
+    fn is_terminated(&self) -> bool {
+        self.handle.borrow().is_terminated()
+    }
+}
+
+/// Weak handle to a [`Shared`], created by [`downgrade`](SharedStream::downgrade).
+pub type WeakShared<S> = WeakSharedStream<S, Local>;
+
+/// Weak handle to an [`Ashared`], created by [`downgrade`](SharedStream::downgrade).
+pub type WeakAshared<S> = WeakSharedStream<S, ThreadSafe>;
+
+/// Weak handle to a shared stream, which doesn't keep it alive. Prefer the aliases
+/// [`WeakShared`] and [`WeakAshared`] over naming this type.
+pub struct WeakSharedStream<S: Stream, M: Storage<S>> {
+    inner: M::Weak,
+    idx: usize,
+    generation: usize,
+}
+
+impl<S: Stream, M: Storage<S>> WeakSharedStream<S, M> {
+    /// Creates a handle from this weak handle, unless all handles have been dropped.
+    ///
+    /// The new handle starts at the position of the handle this was downgraded from, or at the
+    /// first buffered item if the items it would yield next have been dropped.
+    #[must_use]
+    pub fn upgrade(&self) -> Option<SharedStream<S, M>> {
+        let inner = M::upgrade(&self.inner)?;
+        let mut state = M::write(&inner);
+        let idx = state.cursor(self.generation, self.idx);
+        let position = state.register(idx);
+        drop(state);
+        Some(SharedStream {
+            inner,
+            idx: self.idx,
+            generation: self.generation,
+            position,
+        })
+    }
+}
+
+impl<S: Stream, M: Storage<S>> Clone for WeakSharedStream<S, M> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            idx: self.idx,
+            generation: self.generation,
+        }
+    }
+}
+
+impl<S: Stream, M: Storage<S>> fmt::Debug for WeakSharedStream<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WeakSharedStream")
+            .field("idx", &self.idx)
+            .field("generation", &self.generation)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Stream for the [`zip_shared`](SharedStream::zip_shared) method.
+pub type ZipShared<S, T, M> = SharedStream<Zip<SharedStream<S, M>, SharedStream<T, M>>, M>;
+
+/// Stream for the [`try_shared`](Share::try_shared) method.
+pub type TryShared<S> = Shared<UntilErr<S>>;
+
+/// `Ok` side of the [`shared_split`](Share::shared_split) method.
+pub type SharedOks<S> = Shared<SplitOk<Shared<S>>>;
+
+/// `Err` side of the [`shared_split`](Share::shared_split) method.
+pub type SharedErrs<S> = Shared<SplitErr<Shared<S>>>;
+
+/// Shared stream returned by the [`shared_boxed`](Share::shared_boxed) method.
+pub type SharedBoxed<T> = Shared<LocalBoxStream<'static, T>>;
+
+/// Shared stream returned by the [`ashared_boxed`](Share::ashared_boxed) method.
+///
+/// The boxed stream is [`Sync`] as well, so handles can be sent to other threads.
+pub type AsharedBoxed<T> = Ashared<Pin<Box<dyn Stream<Item = T> + Send + Sync>>>;
+
+/// Underlying stream of the [`shared_rc`](Share::shared_rc) method.
+pub type WrapRc<S> = Map<S, fn(<S as Stream>::Item) -> Rc<<S as Stream>::Item>>;
+
+/// Underlying stream of the [`ashared_arc`](Share::ashared_arc) method.
+pub type WrapArc<S> = Map<S, fn(<S as Stream>::Item) -> Arc<<S as Stream>::Item>>;
+
+/// An extension trait implemented for [`Stream`]s that provides the [`shared`](Share::shared) and [`ashared`](Share::ashared) methods.
+pub trait Share: Stream {
+    /// Turns this stream into a cloneable stream. Polled items are cached and cloned.
+    ///
+    /// Note that this function consumes the stream passed into it and returns a wrapped version of it.
+    fn shared(self) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Turns this stream into a cloneable stream that can be shared across threads. Polled items are cached and cloned.
+    ///
+    /// Note that this function consumes the stream passed into it and returns a wrapped version of it.
+    fn ashared(self) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but borrows the stream instead of consuming it, so it can
+    /// be used again once all handles are dropped.
+    ///
+    /// The handles borrow the stream mutably, so they can't outlive the borrow, and the stream
+    /// can't be used while any of them is alive. The stream is only polled as far as the handles
+    /// need, and continues after the last item it produced for them. Items that have been
+    /// buffered but not yielded by every handle are dropped along with the last handle.
+    fn shared_by_ref(&mut self) -> Shared<&mut Self>
+    where
+        Self: Unpin,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but boxes the stream first, so shared streams of the same
+    /// item type have the same type, e.g. to keep different ones in a [`Vec`].
+    fn shared_boxed(self) -> SharedBoxed<Self::Item>
+    where
+        Self: Sized + 'static,
+        Self::Item: Clone;
+
+    /// Like [`ashared`](Share::ashared), but boxes the stream first, so shared streams of the
+    /// same item type have the same type, e.g. to keep different ones in a [`Vec`].
+    fn ashared_boxed(self) -> AsharedBoxed<Self::Item>
+    where
+        Self: Sized + Send + Sync + 'static,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but the underlying stream is polled for at most `n` items.
+    /// The underlying stream is dropped as soon as it has produced the last of them, and every
+    /// handle finishes once it has yielded those items.
+    ///
+    /// In contrast to calling `take` on a single handle, this limits all handles and stops
+    /// polling the underlying stream.
+    fn shared_take(self, n: usize) -> Shared<Take<Self>>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but pairs every item with its index in the underlying
+    /// stream.
+    ///
+    /// In contrast to calling `enumerate` on a handle, which counts the items that handle has
+    /// yielded, the index is the same for an item no matter which handle yields it.
+    fn shared_enumerate(self) -> Shared<Enumerate<Self>>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but maps every item with `f` as it is produced.
+    ///
+    /// In contrast to calling `map` on every handle, `f` is called once per item regardless of
+    /// the number of handles, and the mapped items are buffered instead of the original ones.
+    fn shared_map<T, F>(self, f: F) -> Shared<Map<Self, F>>
+    where
+        Self: Sized,
+        T: Clone,
+        F: FnMut(Self::Item) -> T;
+
+    /// Like [`shared`](Share::shared), but wraps every item in an [`Rc`] once as it is produced,
+    /// so handles yield cheap pointer clones instead of cloning the items themselves.
+    ///
+    /// This is worth it for large items like buffers or strings, and works for items that don't
+    /// implement [`Clone`] at all. The trade-off is an extra allocation per item and getting
+    /// shared references instead of owned items.
+    fn shared_rc(self) -> Shared<WrapRc<Self>>
+    where
+        Self: Sized;
+
+    /// Like [`shared_rc`](Share::shared_rc), but wraps the items in an [`Arc`] and creates a
+    /// cloneable stream that can be shared across threads.
+    fn ashared_arc(self) -> Ashared<WrapArc<Self>>
+    where
+        Self: Sized + Send;
+
+    /// Like [`shared`](Share::shared), but for streams of [`Result`]s, which end after the first
+    /// error.
+    ///
+    /// Items are buffered until the underlying stream yields an `Err`. That error is buffered
+    /// too, so every handle yields it exactly once, after the items before it. Afterwards, the
+    /// underlying stream is dropped without being polled again and all handles finish.
+    fn try_shared<T, E>(self) -> TryShared<Self>
+    where
+        Self: Stream<Item = Result<T, E>> + Sized,
+        T: Clone,
+        E: Clone;
+
+    /// Splits a stream of [`Result`]s into a cloneable stream of the `Ok` values and one of the
+    /// `Err` values.
+    ///
+    /// The underlying stream is polled once for both sides, by whichever side needs its next
+    /// item. Each side buffers only its own values, while items are kept in between until both
+    /// sides have passed them, so a side that is never polled makes the other one buffer
+    /// everything.
+    fn shared_split<T, E>(self) -> (SharedOks<Self>, SharedErrs<Self>)
+    where
+        Self: Stream<Item = Result<T, E>> + Sized,
+        T: Clone,
+        E: Clone;
+
+    /// Like [`shared`](Share::shared), but yields a clone of `separator` between every two items.
+    ///
+    /// The separators are inserted once as items are produced, so all handles see the same
+    /// sequence. Note that the separators are buffered like regular items, roughly doubling the
+    /// number of buffered items.
+    fn shared_intersperse(self, separator: Self::Item) -> Shared<Intersperse<Self>>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but merges adjacent items with `f`, like
+    /// `itertools::coalesce`.
+    ///
+    /// `f` is called with the item built so far and the next item. Returning `Ok` replaces both
+    /// with the merged item, returning `Err` yields the first one and continues with the second.
+    /// This is done once as items are produced, so all handles see the same sequence. Note that
+    /// an item only becomes available to the handles once `f` has rejected the item after it or
+    /// the underlying stream has finished.
+    fn shared_coalesce<F>(self, f: F) -> Shared<Coalesce<Self, F>>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        F: FnMut(Self::Item, Self::Item) -> Result<Self::Item, (Self::Item, Self::Item)>;
+
+    /// Like [`shared`](Share::shared), but skips items that equal the previous item.
+    ///
+    /// Items are compared once as they are produced, regardless of the number of handles, so all
+    /// handles see the same sequence. The last item is cloned to compare it with the next one.
+    /// See [`shared_dedup_by_key`](Share::shared_dedup_by_key) to compare only part of the items.
+    fn shared_dedup(self) -> Shared<Dedup<Self>>
+    where
+        Self: Sized,
+        Self::Item: PartialEq + Clone;
+
+    /// Like [`shared`](Share::shared), but skips items whose key, as returned by `f`, equals
+    /// the key of the previous item.
+    ///
+    /// `f` is called once per item of the underlying stream as items are produced, regardless
+    /// of the number of handles, so all handles see the same sequence. Since any number of items
+    /// might be skipped, the upper bound of `size_hint` is only a loose bound.
+    fn shared_dedup_by_key<K, F>(self, f: F) -> Shared<DedupByKey<Self, F, K>>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        K: PartialEq,
+        F: FnMut(&Self::Item) -> K;
+
+    /// Like [`shared`](Share::shared), but ends with the first item `f` returns `false` for,
+    /// which is dropped.
+    ///
+    /// `f` is called once per item of the underlying stream as items are produced, regardless of
+    /// the number of handles, so all handles end at the same item. The underlying stream isn't
+    /// polled after that.
+    fn shared_take_while<F>(self, f: F) -> Shared<TakeWhile<Self, F>>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        F: FnMut(&Self::Item) -> bool;
+
+    /// Like [`shared`](Share::shared), but shares the outputs of `f`, which is called with the
+    /// state `init` and each item, until it returns `None`, like [`scan`](StreamExt::scan) but
+    /// with a synchronous `f`.
+    ///
+    /// `f` is called once per item of the underlying stream as items are produced, regardless of
+    /// the number of handles, so the state only advances once per item and all handles see the
+    /// same outputs. The underlying stream isn't polled after `f` has returned `None`.
+    fn shared_scan<St, T, F>(self, init: St, f: F) -> Shared<Scan<Self, St, F>>
+    where
+        Self: Sized,
+        T: Clone,
+        F: FnMut(&mut St, Self::Item) -> Option<T>;
+
+    /// Like [`shared`](Share::shared), but only keeps the items `f` returns `true` for.
+    ///
+    /// `f` is called once per item of the underlying stream as items are produced, regardless of
+    /// the number of handles, instead of once per handle as with [`filter`](StreamExt::filter)
+    /// on every handle. Rejected items are dropped right away and never buffered.
+    fn shared_filter<F>(self, f: F) -> Shared<Filter<Self, F>>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        F: FnMut(&Self::Item) -> bool;
+
+    /// Like [`shared`](Share::shared), but drops buffered items once every handle has yielded
+    /// them, so a long-lived shared stream whose handles keep up doesn't grow without bound.
+    ///
+    /// Handles created afterwards with `clone` start at the position of the cloned handle, so
+    /// they never need a dropped item. Items are dropped in batches as the underlying stream
+    /// produces new ones, so the memory of dropped handles is freed lazily.
+    fn shared_reclaiming(self) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared_reclaiming`](Share::shared_reclaiming), but creates a cloneable stream
+    /// that can be shared across threads.
+    fn ashared_reclaiming(self) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but every handle registers its position, so that
+    /// [`consumer_positions`](SharedStream::consumer_positions) can report it.
+    ///
+    /// This makes cloning and dropping handles a bit more expensive, which is why plain shared
+    /// streams don't do it. Variants that need the positions anyway, like
+    /// [`shared_reclaiming`](Share::shared_reclaiming), track them as well.
+    fn shared_tracking(self) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared_tracking`](Share::shared_tracking), but creates a cloneable stream that can
+    /// be shared across threads.
+    fn ashared_tracking(self) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`shared_reclaiming`](Share::shared_reclaiming), but lets the fastest handle get at
+    /// most `cap` items ahead of the slowest one.
+    ///
+    /// Once the slowest handle has `cap` unread items, polling for a new item returns
+    /// [`Poll::Pending`] until the slowest handle advances or is dropped, which applies
+    /// backpressure to the underlying stream. Reclamation is always enabled, since otherwise the
+    /// buffer would still grow without bound. Note that a handle that is never polled and never
+    /// dropped blocks all other handles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero.
+    fn shared_with_capacity(self, cap: usize) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared_with_capacity`](Share::shared_with_capacity), but creates a cloneable stream
+    /// that can be shared across threads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `cap` is zero.
+    fn ashared_with_capacity(self, cap: usize) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`ashared`](Share::ashared), but limits the number of handles created with
+    /// [`try_clone`](Ashared::try_clone) to `cap`, including the returned one.
+    fn ashared_max_subscribers(self, cap: usize) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`ashared`](Share::ashared), but for streams that aren't [`Send`], which are kept on
+    /// the thread calling this.
+    ///
+    /// Handles can be sent to other threads to read items that have already been produced, but
+    /// only handles on this thread may poll the underlying stream for new ones: polling a handle
+    /// on another thread panics if it has to poll the underlying stream. If the last handle is
+    /// dropped on another thread, the underlying stream is leaked instead of dropped there.
+    #[cfg(feature = "std")]
+    fn ashared_local(self) -> Ashared<ThreadBound<Self>>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`ashared`](Share::ashared), but makes the handles take turns polling the
+    /// underlying stream.
+    ///
+    /// A handle that polled the underlying stream last gives up its turn while other handles are
+    /// being polled, returning [`Poll::Pending`] until one of them is done. This keeps a thread
+    /// polling its handle in a tight loop from monopolizing the underlying stream while other
+    /// threads wait for the lock, at the cost of extra wake-ups.
+    fn ashared_fair(self) -> Ashared<Self>
+    where
+        Self: Sized + Send,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but only keeps the `n` most recently produced items.
+    ///
+    /// Older items are dropped even if some handles haven't yielded them yet. Such handles, as
+    /// well as handles cloned from them, continue with the oldest item that is still buffered.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero.
+    fn shared_replay_last(self, n: usize) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but reserves buffer capacity ahead of time according to
+    /// `policy`, to reduce how often a fast producer has to wait for the buffer to be
+    /// reallocated.
+    fn shared_with_growth_policy(self, policy: GrowthPolicy) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but reserves room for `capacity` items in the buffer up
+    /// front.
+    ///
+    /// If the number of items the stream produces is roughly known, this avoids reallocating
+    /// the buffer while it fills up. [`shared`](Share::shared) is the same as
+    /// `shared_with_buffer(0)`.
+    fn shared_with_buffer(self, capacity: usize) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but polls the underlying stream at most `budget` times
+    /// each time a handle or [`pump`](SharedStream::pump) is polled.
+    ///
+    /// A handle that needs more items than that, e.g. after [seeking](SharedStream::seek) far
+    /// ahead, wakes its own task and returns [`Poll::Pending`] instead, so an underlying stream
+    /// that keeps producing items synchronously can't keep other tasks from running.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `budget` is zero.
+    fn shared_with_budget(self, budget: usize) -> Shared<Self>
+    where
+        Self: Sized,
+        Self::Item: Clone;
+
+    /// Like [`shared`](Share::shared), but duplicates items for the handles by calling `clone_fn`
+    /// instead of [`Clone::clone`], so the items don't have to implement [`Clone`].
+    ///
+    /// `clone_fn` is stored once for the whole shared stream and called whenever a handle yields
+    /// an item, just like `clone` would be. Buffered items themselves are never passed by value.
+    fn shared_with<F>(self, clone_fn: F) -> Shared<Self>
+    where
+        Self: Sized,
+        F: Fn(&Self::Item) -> Self::Item + 'static;
+
+    /// Like [`shared`](Share::shared), but calls `on_drop` once the underlying stream has been
+    /// dropped.
+    ///
+    /// That is the case once the underlying stream has finished, or once all handles have been
+    /// dropped before that, whichever comes first. `on_drop` is called exactly once, right after
+    /// dropping the stream, which makes it suitable for cleanup like closing a connection.
+    fn shared_with_drop<F>(self, on_drop: F) -> Shared<OnDrop<Self, F>>
+    where
+        Self: Sized,
+        Self::Item: Clone,
+        F: FnOnce();
+
+    /// Turns this stream into a cloneable stream, creating a [`Shared`] or an [`Ashared`]
+    /// handle depending on `M`. See [`SharedHandle`] for why this is useful.
+    fn shared_with_mode<M: ShareMode<Self>>(self) -> SharedHandle<Self>
+    where
+        Self: Sized,
+    {
+        M::share(self)
+    }
+}
+
+impl<T: Stream> Share for T {
+    fn shared(self) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        self.shared_with_buffer(0)
+    }
+
+    fn ashared(self) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::new(self)
+    }
+
+    fn shared_by_ref(&mut self) -> Shared<&mut Self>
+    where
+        T: Unpin,
+        Self::Item: Clone,
+    {
+        self.shared()
+    }
+
+    fn shared_boxed(self) -> SharedBoxed<Self::Item>
+    where
+        T: 'static,
+        Self::Item: Clone,
+    {
+        self.boxed_local().shared()
+    }
+
+    fn ashared_boxed(self) -> AsharedBoxed<Self::Item>
+    where
+        T: Send + Sync + 'static,
+        Self::Item: Clone,
+    {
+        let boxed: Pin<Box<dyn Stream<Item = Self::Item> + Send + Sync>> = Box::pin(self);
+        boxed.ashared()
+    }
+
+    fn shared_take(self, n: usize) -> Shared<Take<Self>>
+    where
+        Self::Item: Clone,
+    {
+        Shared::new(Take::new(self, n))
+    }
+
+    fn shared_enumerate(self) -> Shared<Enumerate<Self>>
+    where
+        Self::Item: Clone,
+    {
+        Shared::new(self.enumerate())
+    }
+
+    fn shared_map<U, F>(self, f: F) -> Shared<Map<Self, F>>
+    where
+        U: Clone,
+        F: FnMut(Self::Item) -> U,
+    {
+        Shared::new(self.map(f))
+    }
+
+    fn shared_rc(self) -> Shared<WrapRc<Self>> {
+        let wrap: fn(Self::Item) -> Rc<Self::Item> = Rc::new;
+        Shared::new(self.map(wrap))
+    }
+
+    fn ashared_arc(self) -> Ashared<WrapArc<Self>>
+    where
+        T: Send,
+    {
+        let wrap: fn(Self::Item) -> Arc<Self::Item> = Arc::new;
+        Ashared::new(self.map(wrap))
+    }
+
+    fn try_shared<U, E>(self) -> TryShared<Self>
+    where
+        Self: Stream<Item = Result<U, E>>,
+        U: Clone,
+        E: Clone,
+    {
+        Shared::new(UntilErr::new(self))
+    }
+
+    fn shared_split<U, E>(self) -> (SharedOks<Self>, SharedErrs<Self>)
+    where
+        Self: Stream<Item = Result<U, E>>,
+        U: Clone,
+        E: Clone,
+    {
+        let source = self.shared_reclaiming();
+        (
+            Shared::new(SplitOk::new(source.clone())),
+            Shared::new(SplitErr::new(source)),
+        )
+    }
+
+    fn shared_intersperse(self, separator: Self::Item) -> Shared<Intersperse<Self>>
+    where
+        Self::Item: Clone,
+    {
+        Shared::new(Intersperse::new(self, separator))
+    }
+
+    fn shared_coalesce<F>(self, f: F) -> Shared<Coalesce<Self, F>>
+    where
+        Self::Item: Clone,
+        F: FnMut(Self::Item, Self::Item) -> Result<Self::Item, (Self::Item, Self::Item)>,
+    {
+        Shared::new(Coalesce::new(self, f))
+    }
+
+    fn shared_dedup(self) -> Shared<Dedup<Self>>
+    where
+        Self::Item: PartialEq + Clone,
+    {
+        Shared::new(Dedup::new(self))
+    }
+
+    fn shared_dedup_by_key<K, F>(self, f: F) -> Shared<DedupByKey<Self, F, K>>
+    where
+        Self::Item: Clone,
+        K: PartialEq,
+        F: FnMut(&Self::Item) -> K,
+    {
+        Shared::new(DedupByKey::new(self, f))
+    }
+
+    fn shared_take_while<F>(self, f: F) -> Shared<TakeWhile<Self, F>>
+    where
+        Self::Item: Clone,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Shared::new(TakeWhile::new(self, f))
+    }
+
+    fn shared_scan<St, U, F>(self, init: St, f: F) -> Shared<Scan<Self, St, F>>
+    where
+        U: Clone,
+        F: FnMut(&mut St, Self::Item) -> Option<U>,
+    {
+        Shared::new(Scan::new(self, init, f))
+    }
+
+    fn shared_filter<F>(self, f: F) -> Shared<Filter<Self, F>>
+    where
+        Self::Item: Clone,
+        F: FnMut(&Self::Item) -> bool,
+    {
+        Shared::new(Filter::new(self, f))
+    }
+
+    fn shared_reclaiming(self) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        Shared::from_inner(InnerState {
+            reclaim: true,
+            ..InnerState::new(self)
+        })
+    }
+
+    fn ashared_reclaiming(self) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::from_inner(InnerState {
+            reclaim: true,
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_tracking(self) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        Shared::from_inner(InnerState {
+            track_positions: true,
+            ..InnerState::new(self)
+        })
+    }
+
+    fn ashared_tracking(self) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::from_inner(InnerState {
+            track_positions: true,
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_with_capacity(self, cap: usize) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        Shared::from_inner(InnerState::bounded(self, cap))
+    }
+
+    fn ashared_with_capacity(self, cap: usize) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::from_inner(InnerState::bounded(self, cap))
+    }
+
+    fn ashared_max_subscribers(self, cap: usize) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::from_inner(InnerState {
+            max_handles: cap,
+            ..InnerState::new(self)
+        })
+    }
+
+    #[cfg(feature = "std")]
+    fn ashared_local(self) -> Ashared<ThreadBound<Self>>
+    where
+        Self::Item: Clone,
+    {
+        Ashared::new(ThreadBound::new(self))
+    }
+
+    fn ashared_fair(self) -> Ashared<Self>
+    where
+        T: Send,
+        Self::Item: Clone,
+    {
+        Ashared::from_inner(InnerState {
+            fairness: Some(Arc::new(Fairness::new())),
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_replay_last(self, n: usize) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        assert!(n > 0, "number of items to keep must be non-zero");
+        Shared::from_inner(InnerState {
+            replay_last: Some(n),
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_with_growth_policy(self, policy: GrowthPolicy) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        Shared::from_inner(InnerState {
+            growth: Some(Growth::new(policy)),
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_with_buffer(self, capacity: usize) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        Shared::from_inner(InnerState {
+            values: Vec::with_capacity(capacity),
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_with_budget(self, budget: usize) -> Shared<Self>
+    where
+        Self::Item: Clone,
+    {
+        assert!(budget != 0, "budget must be non-zero");
+        Shared::from_inner(InnerState {
+            budget: Some(budget),
+            ..InnerState::new(self)
+        })
+    }
+
+    fn shared_with<F>(self, clone_fn: F) -> Shared<Self>
+    where
+        F: Fn(&Self::Item) -> Self::Item + 'static,
+    {
+        let clone_fn: <Local as Storage<Self>>::CloneFn = Box::new(clone_fn);
+        Shared::from_inner(InnerState::with_cloner(self, Cloner::With(clone_fn)))
+    }
+
+    fn shared_with_drop<F>(self, on_drop: F) -> Shared<OnDrop<Self, F>>
+    where
+        Self::Item: Clone,
+        F: FnOnce(),
+    {
+        Shared::new(OnDrop::new(self, on_drop))
+    }
+}
+
+/// Creates a cloneable stream whose underlying stream is only created by calling `f` once any
+/// handle polls it for the first time.
+///
+/// This keeps creating a shared stream cheap when building the underlying stream is expensive
+/// and the shared stream might never be polled. `f` is called at most once.
+pub fn shared_lazy<F, S>(f: F) -> Shared<Lazy<F, S>>
+where
+    F: FnOnce() -> S,
+    S: Stream,
+    S::Item: Clone,
+{
+    Shared::new(Lazy::new(f))
+}
+
+/// Like [`shared_lazy`], but creates a cloneable stream that can be shared across threads.
+pub fn ashared_lazy<F, S>(f: F) -> Ashared<Lazy<F, S>>
+where
+    F: FnOnce() -> S + Send,
+    S: Stream + Send,
+    S::Item: Clone,
+{
+    Ashared::new(Lazy::new(f))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Share;
+    use core::cell::RefCell;
+    use core::ops::Range;
+    use futures::executor::block_on;
+    use futures::future;
+    use futures::stream::{self, StreamExt};
+    use futures_core::stream::{FusedStream, Stream};
+    use std::panic::AssertUnwindSafe;
+    use std::sync::RwLock;
+
+    fn collect<V: Clone, S: Stream<Item = V>>(stream: S) -> Vec<V> {
+        block_on(stream.collect::<Vec<_>>())
+    }
+
+    #[allow(clippy::needless_pass_by_value, trivial_casts)]
+    fn test_everything<
+        S: Clone + Stream<Item = String> + FusedStream + Unpin,
+        F: Fn() -> Vec<String>,
+    >(
+        orig_stream: S,
+        seen: F,
+    ) {
+        assert_eq!(seen(), [] as [String; 0]);
+        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
+        assert!(!orig_stream.is_terminated());
+
+        let stream = orig_stream.clone().take(1);
+        assert_eq!(stream.size_hint(), (1, Some(1)));
+        assert!(!stream.is_terminated());
+        let result = collect(stream);
+        assert_eq!(result, ["a"]);
+        assert_eq!(seen(), ["a"]);
+        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
+        assert!(!orig_stream.is_terminated());
+
+        let stream = orig_stream.clone();
+        assert_eq!(stream.size_hint(), (3, Some(3)));
+        assert!(!stream.is_terminated());
+        let result = collect(stream);
+        assert_eq!(result, ["a", "b", "c"]);
+        assert_eq!(seen(), ["a", "b", "c"]);
+        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
+        assert!(!orig_stream.is_terminated());
+
+        let stream1 = orig_stream.clone().skip(1);
+        assert_eq!(stream1.size_hint(), (2, Some(2)));
+        assert!(!stream1.is_terminated());
+        let stream2 = orig_stream.clone();
+        assert_eq!(stream2.size_hint(), (3, Some(3)));
+        assert!(!stream2.is_terminated());
+        let (result1, result2): (Vec<_>, Vec<_>) =
+            block_on(future::join(stream1.collect(), stream2.collect()));
+        assert_eq!(result1, ["b", "c"]);
+        assert_eq!(result2, ["a", "b", "c"]);
+        assert_eq!(seen(), ["a", "b", "c"]);
+        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
+        assert!(!orig_stream.is_terminated());
+
+        let mut stream1 = orig_stream.clone();
+        assert_eq!(Some("a".to_string()), block_on(stream1.next()));
+        assert_eq!(stream1.size_hint(), (2, Some(2)));
+        assert!(!stream1.is_terminated());
+        assert_eq!(Some("b".to_string()), block_on(stream1.next()));
+        assert_eq!(stream1.size_hint(), (1, Some(1)));
+        assert!(!stream1.is_terminated());
+        assert_eq!(Some("c".to_string()), block_on(stream1.next()));
+        assert_eq!(stream1.size_hint(), (0, Some(0)));
+        assert!(stream1.is_terminated());
+        assert_eq!(orig_stream.size_hint(), (3, Some(3)));
+        assert!(!orig_stream.is_terminated());
+    }
+
+    #[test]
     fn test_everything_shared() {
         let seen = RefCell::new(vec![]);
         let orig_stream = stream::iter(["a", "b", "c"].iter().map(|v| (*v).to_string()))
@@ -448,28 +2139,1866 @@ mod test {
                 seen.borrow_mut().push(v.clone());
             })
             .shared();
-        test_everything(orig_stream, || seen.borrow().clone());
+        test_everything(orig_stream, || seen.borrow().clone());
+    }
+
+    #[test]
+    fn test_everything_ashared() {
+        let seen = RwLock::new(vec![]);
+        let orig_stream = stream::iter(["a", "b", "c"].iter().map(|v| (*v).to_string()))
+            .inspect(|v| {
+                seen.write().unwrap().push(v.clone());
+            })
+            .ashared();
+        test_everything(orig_stream, || seen.read().unwrap().clone());
+    }
+
+    #[test]
+    fn test_size_hint_for_unfinished() {
+        let mut stream = stream::iter(["a", "b", "c"].iter().map(|v| (*v).to_string())).shared();
+        assert_eq!(stream.size_hint(), (3, Some(3)));
+        assert!(!stream.is_terminated());
+        assert_eq!(block_on(stream.next()), Some("a".to_string()));
+        assert_eq!(stream.size_hint(), (2, Some(2)));
+        assert!(!stream.is_terminated());
+    }
+
+    #[test]
+    fn test_size_hint_saturates() {
+        let mut shared = stream::iter(0..3).shared();
+        assert_eq!(collect(shared.clone()), [0, 1, 2]);
+        shared.seek(3);
+        assert_eq!(shared.size_hint(), (0, Some(0)));
+        shared.seek(usize::MAX);
+        assert_eq!(shared.size_hint(), (0, Some(0)));
+        assert!(shared.is_terminated());
+
+        // seeking past the items the underlying stream can still produce
+        let mut shared = stream::iter(0..3).shared();
+        shared.seek(usize::MAX);
+        assert_eq!(shared.size_hint(), (0, Some(0)));
+
+        // a handle lagging behind items that have been dropped
+        let mut shared = stream::iter(0..10).shared_replay_last(2);
+        let lagging = shared.clone();
+        assert_eq!(
+            block_on((&mut shared).take(5).collect::<Vec<_>>()),
+            [0, 1, 2, 3, 4]
+        );
+        assert_eq!(lagging.size_hint(), (7, Some(7)));
+    }
+
+    #[test]
+    fn test_size_hint_is_invariant() {
+        // items moving from the underlying stream into the buffer aren't counted twice
+        let mut leader = stream::iter(0..5).shared();
+        let lagging = leader.clone();
+        for i in 0..5 {
+            assert_eq!(lagging.size_hint(), (5, Some(5)));
+            assert_eq!(leader.size_hint(), (5 - i, Some(5 - i)));
+            assert_eq!(block_on(leader.next()), Some(i));
+        }
+        assert_eq!(block_on(leader.next()), None);
+        assert_eq!(lagging.size_hint(), (5, Some(5)));
+
+        // underlying streams without an upper bound
+        let unbounded = stream::iter(0..3).chain(stream::poll_fn(|_| Poll::Ready(None)));
+        let mut leader = unbounded.shared();
+        let lagging = leader.clone();
+        for i in 0..3 {
+            assert_eq!(lagging.size_hint(), (3, None));
+            assert_eq!(block_on(leader.next()), Some(i));
+        }
+        assert_eq!(block_on(leader.next()), None);
+        assert_eq!(lagging.size_hint(), (3, Some(3)));
+    }
+
+    #[test]
+    fn test_size_hint_with_reclaiming() {
+        let mut fast = stream::iter(0..20).shared_reclaiming();
+        let mut slow = fast.clone();
+        let mut reclaimed = 0;
+        for step in 0..5 {
+            assert_eq!(collect((&mut fast).take(4)).len(), 4);
+            assert_eq!(collect((&mut slow).take(3)).len(), 3);
+            let base = fast.inner.borrow().reclaimed;
+            assert!(base >= reclaimed);
+            reclaimed = base;
+            // positions are absolute, so the hints don't depend on how many items were dropped
+            assert_eq!(fast.size_hint(), (16 - 4 * step, Some(16 - 4 * step)));
+            assert_eq!(slow.size_hint(), (17 - 3 * step, Some(17 - 3 * step)));
+        }
+        assert!(reclaimed > 0);
+        assert_eq!(fast.size_hint(), (0, Some(0)));
+        assert_eq!(collect(&mut slow).len(), 5);
+        assert_eq!(slow.size_hint(), (0, Some(0)));
+    }
+
+    #[test]
+    fn test_into_vec() {
+        let shared = stream::iter(1..=3).shared();
+        let mut clone = shared.clone();
+        assert_eq!(block_on(clone.next()), Some(1));
+        // still running and not the sole owner
+        let shared = shared.into_vec().unwrap_err();
+        assert_eq!(collect(clone), [2, 3]);
+        assert_eq!(shared.into_vec().unwrap(), [1, 2, 3]);
+
+        let mut shared = stream::iter(1..=3).shared();
+        assert_eq!(block_on(shared.next()), Some(1));
+        // sole owner but still running
+        let shared = shared.into_vec().unwrap_err();
+        assert_eq!(collect(shared.clone()), [2, 3]);
+        assert_eq!(shared.into_vec().unwrap(), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_try_into_iter() {
+        let mut shared = stream::iter(1..=3).shared();
+        assert_eq!(block_on(shared.next()), Some(1));
+        // still running
+        let shared = shared.try_into_iter().unwrap_err();
+        let clone = shared.clone();
+        assert_eq!(collect(clone.clone()), [2, 3]);
+        // not the sole owner
+        let shared = shared.try_into_iter().unwrap_err();
+        drop(clone);
+        assert_eq!(shared.try_into_iter().unwrap().collect::<Vec<_>>(), [2, 3]);
+
+        let mut shared = stream::iter(1..=3).shared();
+        shared.seek(5);
+        assert_eq!(block_on(shared.next()), None);
+        assert_eq!(shared.try_into_iter().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_into_vec_ashared() {
+        let shared = stream::iter(1..=3).ashared();
+        let clone = shared.clone();
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        // finished but not the sole owner
+        let shared = shared.into_vec().unwrap_err();
+        drop(clone);
+        assert_eq!(shared.into_vec().unwrap(), [1, 2, 3]);
+
+        let shared = stream::iter(1..=3).ashared();
+        assert!(shared.into_vec().is_err());
+    }
+
+    #[test]
+    fn test_item_observers() {
+        use std::sync::atomic::AtomicUsize;
+
+        let log = Arc::new(RwLock::new(vec![]));
+        let count = Arc::new(AtomicUsize::new(0));
+        let shared = stream::iter(1..=3).shared();
+        let log_clone = Arc::clone(&log);
+        shared.add_item_observer(move |v| log_clone.write().unwrap().push(*v));
+        let count_clone = Arc::clone(&count);
+        shared.add_item_observer(move |_| {
+            let _ = count_clone.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(*log.read().unwrap(), [1, 2, 3]);
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_item_observers_ashared() {
+        let log = Arc::new(RwLock::new(vec![]));
+        let shared = stream::iter(1..=3).ashared();
+        let mut first = shared.clone();
+        assert_eq!(block_on(first.next()), Some(1));
+        let log_clone = Arc::clone(&log);
+        shared.add_item_observer(move |v| log_clone.write().unwrap().push((1, *v)));
+        let log_clone = Arc::clone(&log);
+        shared.add_item_observer(move |v| log_clone.write().unwrap().push((2, *v)));
+
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(collect(first), [2, 3]);
+        // only items produced after registering, in registration order
+        assert_eq!(*log.read().unwrap(), [(1, 2), (2, 2), (1, 3), (2, 3)]);
+    }
+
+    #[test]
+    fn test_item_observers_local() {
+        use std::rc::Rc;
+
+        // callbacks of `Shared` don't have to be `Send` or `Sync`
+        let log = Rc::new(RefCell::new(vec![]));
+        let shared = stream::iter(1..=3).shared();
+        let log_clone = Rc::clone(&log);
+        shared.add_item_observer(move |v| log_clone.borrow_mut().push(*v));
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(*log.borrow(), [1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "RefCell already")]
+    fn test_item_observer_using_handle() {
+        let shared = stream::iter(1..=3).shared();
+        let weak = shared.downgrade();
+        shared.add_item_observer(move |_| {
+            let _ = weak.upgrade();
+        });
+        let _ = collect(shared);
+    }
+
+    #[test]
+    fn test_shared_take() {
+        use std::rc::Rc;
+
+        let polled = RefCell::new(0);
+        let mut shared = stream::iter(0..)
+            .inspect(|_| *polled.borrow_mut() += 1)
+            .shared_take(2);
+        assert_eq!(shared.size_hint(), (2, Some(2)));
+        let clone = shared.clone();
+        assert_eq!(collect(shared.clone()), [0, 1]);
+        assert_eq!(collect(clone.skip(1)), [1]);
+        assert_eq!(*polled.borrow(), 2);
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(block_on(shared.next()), None);
+        assert!(shared.is_terminated());
+        assert_eq!(*polled.borrow(), 2);
+
+        // the underlying stream is dropped right after its last item
+        let alive = Rc::new(());
+        let weak = Rc::downgrade(&alive);
+        let mut shared = stream::iter(0..)
+            .map(move |v| {
+                let _ = &alive;
+                v
+            })
+            .shared_take(2);
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert!(weak.upgrade().is_some());
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert!(weak.upgrade().is_none());
+        assert_eq!(block_on(shared.next()), None);
+    }
+
+    #[test]
+    fn test_shared_enumerate() {
+        let mut shared = stream::iter(['a', 'b', 'c']).shared_enumerate();
+        assert_eq!(block_on(shared.next()), Some((0, 'a')));
+        let clone = shared.clone();
+        let mut late = clone.clone().skip(1);
+        assert_eq!(block_on(shared.next()), Some((1, 'b')));
+        assert_eq!(block_on(late.next()), Some((2, 'c')));
+        assert_eq!(block_on(shared.next()), Some((2, 'c')));
+        assert_eq!(collect(clone), [(1, 'b'), (2, 'c')]);
+    }
+
+    #[test]
+    fn test_boxed() {
+        use core::pin::Pin;
+
+        let shared = stream::iter(1..=3).ashared();
+        let clone = shared.clone();
+        let mut boxed: Pin<Box<dyn Stream<Item = i32> + Send>> = Box::pin(shared);
+        assert_eq!(block_on(boxed.next()), Some(1));
+        assert_eq!(collect(clone), [1, 2, 3]);
+        assert_eq!(boxed.size_hint(), (2, Some(2)));
+        assert_eq!(collect(boxed), [2, 3]);
+
+        // handles are `Unpin`, so `Box` forwards `Stream` too
+        let mut boxed = Box::new(stream::iter(1..=3).shared());
+        let clone = boxed.clone();
+        assert_eq!(block_on(boxed.next()), Some(1));
+        assert_eq!(collect(clone), [1, 2, 3]);
+        assert_eq!(collect(boxed), [2, 3]);
+    }
+
+    #[test]
+    fn test_estimated_memory() {
+        use super::InnerState;
+        use core::mem::size_of;
+
+        let mut shared = stream::iter(vec![String::from("a"), String::from("bc")]).shared();
+        let state_size =
+            size_of::<InnerState<stream::Iter<std::vec::IntoIter<String>>, super::Local>>();
+        assert_eq!(shared.estimated_memory(), state_size);
+        assert_eq!(block_on(shared.next()).as_deref(), Some("a"));
+        let capacity = shared.inner.borrow().values.capacity();
+        assert!(capacity >= 1);
+        assert_eq!(
+            shared.estimated_memory(),
+            state_size + capacity * size_of::<String>()
+        );
+        assert_eq!(
+            shared.estimated_memory_with(String::capacity),
+            state_size + capacity * size_of::<String>() + 1
+        );
+
+        let shared = stream::iter(0..10u64).ashared();
+        assert_eq!(collect(shared.clone()).len(), 10);
+        let capacity = shared.inner.read().values.capacity();
+        assert!(capacity >= 10);
+        assert_eq!(
+            shared.estimated_memory(),
+            size_of::<InnerState<stream::Iter<Range<u64>>, super::Local>>() + capacity * 8
+        );
+    }
+
+    #[test]
+    fn test_windows() {
+        let mut shared = stream::iter(1..=5).shared();
+        assert_eq!(block_on(shared.next()), Some(1));
+        let windows = shared.clone().windows(2);
+        assert_eq!(windows.size_hint(), (3, Some(3)));
+        assert_eq!(collect(windows), [[2, 3], [3, 4], [4, 5]]);
+        assert_eq!(collect(shared.clone().windows(3)), [[2, 3, 4], [3, 4, 5]]);
+        assert!(collect(shared.clone().windows(5)).is_empty());
+        // windowing doesn't move the other handles
+        assert_eq!(collect(shared), [2, 3, 4, 5]);
+
+        let shared = stream::iter(1..=3).ashared();
+        assert_eq!(collect(shared.windows(1)), [[1], [2], [3]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must be non-zero")]
+    fn test_windows_empty() {
+        let _ = stream::iter(1..=3).shared().windows(0);
+    }
+
+    #[test]
+    fn test_reset_all() {
+        let mut shared = stream::iter(vec![1, 2, 3]).shared();
+        let mut clone = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(collect(&mut clone), [1, 2, 3]);
+        assert!(clone.is_terminated());
+
+        shared.replace_source(stream::iter(vec![4, 5]));
+        shared.reset_all();
+        assert_eq!(shared.size_hint(), (2, Some(2)));
+        assert!(!clone.is_terminated());
+        assert_eq!(block_on(clone.next()), Some(4));
+        assert_eq!(collect(shared), [4, 5]);
+        assert_eq!(collect(clone), [5]);
+    }
+
+    #[test]
+    fn test_replace_source_without_reset() {
+        let shared = stream::iter(vec![1, 2]).ashared();
+        assert_eq!(collect(shared.clone()), [1, 2]);
+        shared.replace_source(stream::iter(vec![3]));
+        assert_eq!(collect(shared), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_with_remaining() {
+        // the filter hides the length of the source from its size hint
+        let shared = stream::iter(1..=3).filter(|_| future::ready(true)).shared();
+        let mut stream = shared.clone().with_remaining();
+        assert_eq!(block_on(stream.next()), Some((1, 0)));
+        // driving another handle buffers the backlog
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(block_on(stream.next()), Some((2, 1)));
+        assert_eq!(block_on(stream.next()), Some((3, 0)));
+        assert_eq!(block_on(stream.next()), None);
+        assert!(stream.is_terminated());
+
+        let stream = stream::iter(1..=3).ashared().with_remaining();
+        assert_eq!(collect(stream), [(1, 2), (2, 1), (3, 0)]);
+    }
+
+    #[test]
+    fn test_shared_cursor() {
+        let shared = stream::iter(1..=4).shared();
+        let cursor = shared.clone().into_shared_cursor();
+        let (mut a, mut b) = (&cursor, &cursor);
+        assert_eq!(block_on(a.next()), Some(1));
+        assert_eq!(block_on(b.next()), Some(2));
+        assert_eq!(b.size_hint(), (2, Some(2)));
+        assert_eq!(
+            block_on(future::join(a.next(), b.next())),
+            (Some(3), Some(4))
+        );
+        assert_eq!(block_on(b.next()), None);
+        assert!(a.is_terminated());
+        // other handles aren't affected
+        assert_eq!(collect(shared), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_shared_lazy() {
+        let calls = RefCell::new(0);
+        let shared = super::shared_lazy(|| {
+            *calls.borrow_mut() += 1;
+            stream::iter(1..=3)
+        });
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (0, None));
+        assert_eq!(*calls.borrow(), 0);
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(*calls.borrow(), 1);
+        assert_eq!(collect(clone), [1, 2, 3]);
+        assert_eq!(*calls.borrow(), 1);
+
+        let shared = super::ashared_lazy(|| stream::iter(1..=3));
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        assert_eq!(collect(shared), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_compact_finished() {
+        let shared = stream::iter(1..=3).shared();
+        shared.inner.borrow_mut().values.reserve(100);
+        shared.compact_finished();
+        assert!(shared.inner.borrow().values.capacity() >= 100);
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        shared.compact_finished();
+        assert_eq!(shared.inner.borrow().values.capacity(), 3);
+        assert_eq!(collect(shared), [1, 2, 3]);
+
+        let shared = stream::iter(1..=3).ashared();
+        shared.inner.write().values.reserve(100);
+        assert_eq!(collect(shared.clone()), [1, 2, 3]);
+        shared.compact_finished();
+        assert_eq!(shared.inner.read().values.capacity(), 3);
+    }
+
+    #[test]
+    fn test_compact_on_finish() {
+        use super::InnerState;
+
+        let mut shared = stream::iter(0..1000).shared();
+        let clone = shared.clone();
+        for i in 0..1000 {
+            assert_eq!(block_on(shared.next()), Some(i));
+        }
+        assert!(shared.inner.borrow().values.capacity() > 1000);
+        assert_eq!(block_on(shared.next()), None);
+        assert_eq!(shared.inner.borrow().values.capacity(), 1000);
+        assert_eq!(
+            shared.estimated_memory(),
+            size_of::<InnerState<stream::Iter<Range<i32>>, super::Local>>()
+                + 1000 * size_of::<i32>()
+        );
+        assert_eq!(collect(clone), (0..1000).collect::<Vec<_>>());
+
+        let ashared = stream::iter(0..1000).ashared();
+        assert_eq!(collect(ashared.clone()).len(), 1000);
+        assert_eq!(ashared.inner.read().values.capacity(), 1000);
+    }
+
+    #[test]
+    fn test_zero_sized_items() {
+        let mut shared = stream::repeat(()).shared();
+        let clone = shared.clone();
+        for _ in 0..1000 {
+            assert_eq!(block_on(shared.next()), Some(()));
+        }
+        assert_eq!(shared.size_hint(), (usize::MAX, None));
+        assert_eq!(clone.size_hint(), (usize::MAX, None));
+        assert_eq!(shared.estimated_memory(), clone.estimated_memory());
+    }
+
+    fn check_zero_sized_items<S>(mut shared: S)
+    where
+        S: Clone + Stream<Item = ()> + FusedStream + Unpin,
+    {
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (1000, Some(1000)));
+        assert_eq!(block_on(shared.next()), Some(()));
+        assert_eq!(shared.size_hint(), (999, Some(999)));
+        assert_eq!(clone.size_hint(), (1000, Some(1000)));
+        assert_eq!(collect(&mut shared).len(), 999);
+        assert!(shared.is_terminated());
+        assert!(!clone.is_terminated());
+        assert_eq!(collect(clone).len(), 1000);
+    }
+
+    #[test]
+    fn test_zero_sized_items_finite() {
+        check_zero_sized_items(stream::repeat(()).take(1000).shared());
+        check_zero_sized_items(stream::repeat(()).take(1000).ashared());
+
+        let shared = stream::iter(vec![(); 3]).shared();
+        assert_eq!(collect(shared.clone().windows(2)), [[(), ()], [(), ()]]);
+        shared.compact_finished();
+        assert_eq!(shared.into_vec().unwrap(), [(); 3]);
+    }
+
+    #[test]
+    fn test_shared_rc() {
+        use std::rc::Rc;
+        use std::sync::Arc;
+
+        // doesn't implement `Clone`
+        #[derive(Debug, PartialEq, Eq)]
+        struct Payload(Vec<u8>);
+
+        let mut shared = stream::iter([Payload(vec![1; 100]), Payload(vec![2; 100])]).shared_rc();
+        let clone = shared.clone();
+        let first = block_on(shared.next()).unwrap();
+        let items = collect(clone);
+        assert!(Rc::ptr_eq(&first, &items[0]));
+        assert_eq!(*items[1], Payload(vec![2; 100]));
+        assert_eq!(collect(shared), [Rc::clone(&items[1])]);
+
+        let ashared = stream::iter([Payload(vec![1]), Payload(vec![2])]).ashared_arc();
+        let items = collect(ashared.clone());
+        assert!(Arc::ptr_eq(&items[0], &collect(ashared)[0]));
+    }
+
+    #[test]
+    fn test_try_shared() {
+        let polls = RefCell::new(0);
+        let source = stream::iter([Ok(1), Ok(2), Err("failed"), Ok(3)]).inspect(|_| {
+            *polls.borrow_mut() += 1;
+        });
+        let mut shared = source.try_shared();
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (1, Some(4)));
+        assert_eq!(collect(&mut shared), [Ok(1), Ok(2), Err("failed")]);
+        assert_eq!(*polls.borrow(), 3);
+        assert!(shared.is_terminated());
+        assert_eq!(collect(clone), [Ok(1), Ok(2), Err("failed")]);
+        assert_eq!(*polls.borrow(), 3);
+
+        let shared = stream::iter([Ok::<_, ()>(1), Ok(2)]).try_shared();
+        assert_eq!(collect(shared), [Ok(1), Ok(2)]);
+    }
+
+    #[test]
+    fn test_shared_intersperse() {
+        let mut shared = stream::iter(1..=3).shared_intersperse(0);
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (5, Some(5)));
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(shared.size_hint(), (4, Some(4)));
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(shared.size_hint(), (3, Some(3)));
+        assert_eq!(collect(clone), [1, 0, 2, 0, 3]);
+        assert_eq!(collect(shared), [2, 0, 3]);
+
+        assert!(collect(stream::iter(0..0).shared_intersperse(0)).is_empty());
+        assert_eq!(collect(stream::iter(1..=1).shared_intersperse(0)), [1]);
+    }
+
+    #[test]
+    fn test_shared_coalesce() {
+        // sums runs of numbers with the same sign
+        let calls = RefCell::new(0);
+        let sum_runs = |a: i32, b: i32| {
+            *calls.borrow_mut() += 1;
+            if a.signum() == b.signum() {
+                Ok(a + b)
+            } else {
+                Err((a, b))
+            }
+        };
+        let mut shared = stream::iter([1, 2, -1, -3, -4, 5, 1]).shared_coalesce(sum_runs);
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (1, Some(7)));
+        assert_eq!(block_on(shared.next()), Some(3));
+        assert_eq!(collect(clone), [3, -8, 6]);
+        assert_eq!(collect(shared), [-8, 6]);
+        assert_eq!(*calls.borrow(), 6);
+
+        let sum_all = |a: i32, b: i32| Ok::<_, (i32, i32)>(a + b);
+        assert!(collect(stream::iter(0..0).shared_coalesce(sum_all)).is_empty());
+        assert_eq!(collect(stream::iter(1..=4).shared_coalesce(sum_all)), [10]);
+    }
+
+    #[test]
+    fn test_untracked_handles() {
+        // handles of plain shared streams don't register anywhere
+        let mut shared = stream::iter(0..3).shared();
+        let clones = [
+            shared.clone(),
+            shared.fork_from_start(),
+            shared.subscribe_live(),
+        ];
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert!(shared.position.is_none());
+        assert!(clones.iter().all(|clone| clone.position.is_none()));
+        assert!(shared.inner.borrow().cursors.is_empty());
+
+        let ashared = stream::iter(0..3).ashared();
+        let clone = ashared.clone();
+        assert!(clone.position.is_none());
+        assert!(ashared.inner.read().cursors.is_empty());
+    }
+
+    #[test]
+    fn test_consumer_positions() {
+        let mut a = stream::iter(1..=5).shared_tracking();
+        let mut b = a.clone();
+        assert_eq!(block_on(a.next()), Some(1));
+        assert_eq!(block_on(a.next()), Some(2));
+        let c = a.clone();
+        assert_eq!(block_on(b.next()), Some(1));
+        let mut positions = a.consumer_positions();
+        positions.sort_unstable();
+        assert_eq!(positions, [1, 2, 2]);
+
+        drop(c);
+        let mut positions = b.consumer_positions();
+        positions.sort_unstable();
+        assert_eq!(positions, [1, 2]);
+
+        a.reset_all();
+        assert_eq!(a.consumer_positions(), [0, 0]);
+
+        // plain shared streams don't track positions
+        let a = stream::iter(1..=5).shared();
+        let _b = a.clone();
+        assert!(a.consumer_positions().is_empty());
+
+        let mut a = stream::iter(1..=5).ashared_tracking();
+        assert_eq!(block_on(a.next()), Some(1));
+        let windows = a.clone().windows(2);
+        assert_eq!(collect(windows), [[2, 3], [3, 4], [4, 5]]);
+        assert_eq!(a.consumer_positions(), [1]);
+    }
+
+    /// Panics in a combinator of one handle while polling it, then checks that the other
+    /// handle still works.
+    fn check_panicking_consumer<S>(shared: S)
+    where
+        S: Stream<Item = i32> + Clone + Unpin,
+    {
+        let other = shared.clone();
+        let mut panicking = shared.map(|v| assert!(v < 2, "consumer panicked"));
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            while block_on(panicking.next()).is_some() {}
+        }));
+        assert!(result.is_err());
+        assert_eq!(collect(other), [0, 1, 2, 3]);
+    }
+
+    /// Lets one handle poll an underlying stream that panics once, then checks that the other
+    /// handle still works.
+    fn check_panicking_source<S>(shared: S)
+    where
+        S: Stream<Item = i32> + Clone + Unpin,
+    {
+        let mut panicking = shared.clone();
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| {
+            while block_on(panicking.next()).is_some() {}
+        }));
+        assert!(result.is_err());
+        assert_eq!(collect(shared), [0, 1, 3]);
+        assert_eq!(collect(panicking), [3]);
+    }
+
+    #[test]
+    fn test_source_panic_is_isolated() {
+        let source = || {
+            stream::iter(0..4).map(|v| {
+                assert!(v != 2, "underlying stream panicked");
+                v
+            })
+        };
+        check_panicking_source(source().shared());
+        check_panicking_source(source().ashared());
+    }
+
+    #[test]
+    fn test_consumer_panic_is_isolated() {
+        check_panicking_consumer(stream::iter(0..4).shared());
+        check_panicking_consumer(stream::iter(0..4).ashared());
+    }
+
+    #[test]
+    fn test_reclaiming() {
+        let mut a = stream::iter(0..1000).shared_reclaiming();
+        let mut b = a.clone();
+        for i in 0..1000 {
+            assert_eq!(block_on(a.next()), Some(i));
+            assert_eq!(block_on(b.next()), Some(i));
+            assert!(a.inner.borrow().values.len() <= 2);
+        }
+        assert_eq!(a.size_hint(), (0, Some(0)));
+        assert_eq!(block_on(a.next()), None);
+        assert!(b.is_terminated());
+        assert_eq!(a.consumer_positions(), [1000, 1000]);
+
+        // a lagging handle keeps its items alive until it is dropped
+        let mut fast = stream::iter(0..100).shared_reclaiming();
+        let slow = fast.clone();
+        for i in 0..50 {
+            assert_eq!(block_on(fast.next()), Some(i));
+        }
+        assert_eq!(fast.inner.borrow().values.len(), 50);
+        let late = fast.clone();
+        drop(slow);
+        assert_eq!(block_on(fast.next()), Some(50));
+        assert_eq!(fast.inner.borrow().values.len(), 1);
+        assert_eq!(late.size_hint(), (50, Some(50)));
+        assert_eq!(collect(late), (50..100).collect::<Vec<_>>());
+        assert_eq!(fast.clone().finished_iter().unwrap().count(), 49);
+
+        fast.reset_all();
+        assert!(collect(fast).is_empty());
+
+        let mut a = stream::iter(0..10).ashared_reclaiming();
+        let b = a.clone();
+        assert_eq!(block_on(a.next()), Some(0));
+        assert_eq!(collect(b), (0..10).collect::<Vec<_>>());
+        assert_eq!(collect(a), (1..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_shared_replay_last() {
+        let mut shared = stream::iter(0..110).shared_replay_last(10);
+        let lagging = shared.clone();
+        for i in 0..100 {
+            assert_eq!(block_on(shared.next()), Some(i));
+        }
+        assert_eq!(shared.inner.borrow().values.len(), 10);
+        // a subscriber cloned from a handle at the start only sees the retained items
+        let late = lagging.clone();
+        assert_eq!(late.size_hint(), (20, Some(20)));
+        assert_eq!(shared.size_hint(), (10, Some(10)));
+        assert_eq!(collect(late.take(10)), (90..100).collect::<Vec<_>>());
+        assert_eq!(collect(lagging.clone()), (90..110).collect::<Vec<_>>());
+        assert_eq!(collect(lagging), (100..110).collect::<Vec<_>>());
+        assert_eq!(collect(shared.clone()), (100..110).collect::<Vec<_>>());
+        assert!(!shared.is_terminated());
+        assert_eq!(shared.finished_iter().unwrap().count(), 10);
+
+        // windows are cloned together as the items are produced, so they may exceed `n`
+        let windows = stream::iter(1..=6).shared_replay_last(2).windows(3);
+        assert_eq!(
+            collect(windows),
+            [[1, 2, 3], [2, 3, 4], [3, 4, 5], [4, 5, 6]]
+        );
+    }
+
+    #[test]
+    fn test_ashared_max_subscribers() {
+        let ashared = stream::iter(0..4).ashared_max_subscribers(5);
+        let clones = std::thread::scope(|scope| {
+            // all threads have to be spawned before joining any of them
+            let mut threads = vec![];
+            for _ in 0..8 {
+                threads.push(scope.spawn(|| {
+                    (0..3)
+                        .filter_map(|_| ashared.try_clone())
+                        .collect::<Vec<_>>()
+                }));
+            }
+            threads
+                .into_iter()
+                .flat_map(|thread| thread.join().unwrap())
+                .collect::<Vec<_>>()
+        });
+        assert_eq!(clones.len(), 4);
+        assert!(ashared.try_clone().is_none());
+
+        let mut clones = clones.into_iter();
+        assert_eq!(collect(clones.next().unwrap()), [0, 1, 2, 3]);
+        let clone = ashared.try_clone().unwrap();
+        assert!(ashared.try_clone().is_none());
+        drop(clone);
+        drop(clones);
+        assert_eq!(collect(ashared.try_clone().unwrap()), [0, 1, 2, 3]);
+        assert_eq!(ashared.consumer_positions().len(), 1);
+    }
+
+    #[test]
+    fn test_shared_dedup() {
+        let shared = stream::iter([1, 1, 2, 2, 2, 3]).shared_dedup();
+        let clones = [shared.clone(), shared.clone()];
+        assert_eq!(shared.size_hint(), (1, Some(6)));
+        assert_eq!(collect(shared), [1, 2, 3]);
+        for clone in clones {
+            assert_eq!(clone.buffered_len(), 3);
+            assert_eq!(collect(clone), [1, 2, 3]);
+        }
+        assert_eq!(
+            collect(stream::iter([1, 2, 1, 1]).shared_dedup()),
+            [1, 2, 1]
+        );
+    }
+
+    #[test]
+    fn test_shared_dedup_by_key() {
+        // (version, value) pairs, where only a new version is relevant
+        let updates = [
+            (1, 'a'),
+            (1, 'b'),
+            (2, 'c'),
+            (2, 'd'),
+            (2, 'e'),
+            (1, 'f'),
+            (3, 'g'),
+        ];
+        let calls = RefCell::new(0);
+        let mut shared = stream::iter(updates).shared_dedup_by_key(|&(version, _)| {
+            *calls.borrow_mut() += 1;
+            version
+        });
+        let clone = shared.clone();
+        assert_eq!(shared.size_hint(), (1, Some(7)));
+        assert_eq!(block_on(shared.next()), Some((1, 'a')));
+        assert_eq!(shared.size_hint(), (0, Some(6)));
+        let expected = [(2, 'c'), (1, 'f'), (3, 'g')];
+        assert_eq!(collect(shared), expected);
+        assert_eq!(collect(clone)[1..], expected);
+        assert_eq!(*calls.borrow(), 7);
+    }
+
+    /// Polls `shared` to the end, returning how often the buffer has been reallocated.
+    fn count_reallocations<S: Stream>(mut shared: super::Shared<S>) -> usize
+    where
+        S::Item: Clone,
+    {
+        let mut reallocations = 0;
+        let mut memory = shared.estimated_memory();
+        while block_on(shared.next()).is_some() {
+            if shared.estimated_memory() != memory {
+                reallocations += 1;
+                memory = shared.estimated_memory();
+            }
+        }
+        reallocations
+    }
+
+    #[test]
+    fn test_growth_policy() {
+        use super::GrowthPolicy;
+
+        let source = || stream::iter(0..10_000_u32);
+        let default = count_reallocations(source().shared());
+        let policy = count_reallocations(source().shared_with_growth_policy(GrowthPolicy::new(4)));
+        assert!(policy < default, "{policy} >= {default}");
+
+        let policy = GrowthPolicy::new(1).threshold_percent(50).min_reserve(100);
+        let mut shared = source().shared_with_growth_policy(policy);
+        let clone = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert!(shared.inner.borrow().values.capacity() >= 102);
+        assert_eq!(collect(clone), (0..10_000).collect::<Vec<_>>());
+        shared.reset_all();
+        assert_eq!(collect(shared).len(), 0);
+    }
+
+    #[test]
+    fn test_shared_with_buffer() {
+        let shared = stream::iter(0..1000).shared_with_buffer(1000);
+        assert_eq!(shared.inner.borrow().values.capacity(), 1000);
+        assert_eq!(count_reallocations(shared.clone()), 0);
+        assert_eq!(collect(shared), (0..1000).collect::<Vec<_>>());
+
+        let shared = stream::iter(0..1000).shared();
+        assert_eq!(shared.inner.borrow().values.capacity(), 0);
+    }
+
+    #[test]
+    fn test_items_are_cloned_once_per_yield() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let clones = Arc::new(AtomicUsize::new(0));
+        let shared = stream::iter(0..5).shared_with({
+            let clones = Arc::clone(&clones);
+            move |&v| {
+                let _ = clones.fetch_add(1, Ordering::SeqCst);
+                v
+            }
+        });
+        let lagging = shared.clone();
+        let mut ahead = shared.clone();
+        ahead.seek(3);
+        assert_eq!(collect(ahead), [3, 4]);
+        assert_eq!(clones.load(Ordering::SeqCst), 2);
+        assert_eq!(collect(shared), [0, 1, 2, 3, 4]);
+        assert_eq!(collect(lagging), [0, 1, 2, 3, 4]);
+        assert_eq!(clones.load(Ordering::SeqCst), 12);
+    }
+
+    #[test]
+    fn test_shared_with() {
+        /// Can only be duplicated by reopening it.
+        #[derive(Debug, PartialEq, Eq)]
+        struct File {
+            id: u32,
+            reopened: bool,
+        }
+
+        let open = |id| File {
+            id,
+            reopened: false,
+        };
+        let mut shared = stream::iter([open(1), open(2)]).shared_with(|file: &File| File {
+            id: file.id,
+            reopened: true,
+        });
+        let reopened = |id| File { id, reopened: true };
+        let clone = shared.clone();
+        assert_eq!(
+            block_on(clone.collect::<Vec<_>>()),
+            [reopened(1), reopened(2)]
+        );
+        assert_eq!(block_on(shared.next()), Some(reopened(1)));
+        assert_eq!(block_on(shared.peek()), Some(reopened(2)));
+        assert_eq!(shared.into_vec().unwrap(), [open(1), open(2)]);
+
+        // the function doesn't have to be `Send` or `Sync`
+        let calls = std::rc::Rc::new(core::cell::Cell::new(0));
+        let counter = std::rc::Rc::clone(&calls);
+        let shared = stream::iter([open(1)]).shared_with(move |file: &File| {
+            counter.set(counter.get() + 1);
+            File {
+                id: file.id,
+                reopened: true,
+            }
+        });
+        assert_eq!(block_on(shared.clone().collect::<Vec<_>>()), [reopened(1)]);
+        assert_eq!(block_on(shared.collect::<Vec<_>>()), [reopened(1)]);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn test_unfused_source_is_not_repolled() {
+        // yields `Some` again after returning `None`
+        let polls = RefCell::new(0);
+        let source = stream::poll_fn(|_| {
+            *polls.borrow_mut() += 1;
+            Poll::Ready((*polls.borrow() != 3).then_some(*polls.borrow()))
+        });
+        let shared = source.shared();
+        let mut clone = shared.clone();
+        assert_eq!(block_on(clone.next()), Some(1));
+        let mut finished = shared.clone();
+        assert_eq!(collect(&mut finished), [1, 2]);
+        for _ in 0..3 {
+            assert_eq!(block_on(finished.next()), None);
+        }
+        assert!(finished.is_terminated());
+        assert_eq!(collect(shared), [1, 2]);
+        assert_eq!(collect(&mut clone), [2]);
+        assert!(clone.is_terminated());
+        assert_eq!(block_on(clone.next()), None);
+        assert_eq!(*polls.borrow(), 3);
+
+        let mut polls = 0;
+        let ashared = stream::poll_fn(move |_| {
+            polls += 1;
+            Poll::Ready((polls % 3 != 0).then_some(polls))
+        })
+        .ashared();
+        let clones = [ashared.clone(), ashared.clone()];
+        assert_eq!(collect(ashared), [1, 2]);
+        for clone in clones {
+            assert_eq!(collect(clone), [1, 2]);
+        }
+    }
+
+    #[test]
+    fn test_buffered_len() {
+        let mut shared = stream::iter(0..5).shared();
+        let clone = shared.clone();
+        assert!(shared.buffered_is_empty());
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(shared.buffered_len(), 2);
+        assert_eq!(clone.buffered_len(), 2);
+        assert_eq!(collect(shared), [2, 3, 4]);
+        assert_eq!(clone.buffered_len(), 5);
+        assert!(!clone.buffered_is_empty());
+
+        let ashared = stream::iter(0..5).ashared();
+        assert!(ashared.buffered_is_empty());
+        assert_eq!(collect(ashared.clone()).len(), 5);
+        assert_eq!(ashared.buffered_len(), 5);
+    }
+
+    #[test]
+    fn test_upstream_finished() {
+        let shared = stream::iter(0..3).shared();
+        let clone = shared.clone();
+        assert!(!clone.upstream_finished());
+        assert_eq!(collect(shared), [0, 1, 2]);
+        assert_eq!(clone.position(), 0);
+        assert!(clone.upstream_finished());
+        assert!(!clone.is_terminated());
+
+        let ashared = stream::iter(0..3).ashared();
+        let clone = ashared.clone();
+        assert_eq!(collect(ashared).len(), 3);
+        assert!(clone.upstream_finished());
+        assert!(!clone.is_terminated());
+    }
+
+    #[test]
+    fn test_zip_shared() {
+        let letters = stream::iter(['a', 'b', 'c']).shared();
+        let mut numbers = stream::iter(0..5).shared();
+        let other = numbers.clone();
+        assert_eq!(block_on(numbers.next()), Some(0));
+        let zipped = letters.zip_shared(numbers);
+        let clone = zipped.clone();
+        assert_eq!(collect(zipped), [('a', 1), ('b', 2), ('c', 3)]);
+        assert_eq!(collect(clone), [('a', 1), ('b', 2), ('c', 3)]);
+        assert_eq!(collect(other), [0, 1, 2, 3, 4]);
+
+        let zipped = stream::iter(0..2)
+            .ashared()
+            .zip_shared(stream::iter(2..5).ashared());
+        assert_eq!(collect(zipped.clone()), [(0, 2), (1, 3)]);
+        assert_eq!(zipped.buffered_len(), 2);
+    }
+
+    #[test]
+    fn test_downgrade() {
+        use std::rc::Rc;
+
+        let token = Rc::new(());
+        let source_token = Rc::clone(&token);
+        let mut shared = stream::iter(0..3)
+            .map(move |v| {
+                let _ = &source_token;
+                v
+            })
+            .shared();
+        assert_eq!(block_on(shared.next()), Some(0));
+        let weak = shared.downgrade();
+        assert_eq!(shared.clone_count(), 1);
+        let mut upgraded = weak.upgrade().unwrap();
+        assert_eq!(block_on(upgraded.next()), Some(1));
+        drop(upgraded);
+        assert_eq!(Rc::strong_count(&token), 2);
+        drop(shared);
+        assert!(weak.upgrade().is_none());
+        assert_eq!(Rc::strong_count(&token), 1);
+
+        let ashared = stream::iter(0..3).ashared();
+        let weak = ashared.downgrade();
+        assert_eq!(collect(weak.upgrade().unwrap()), [0, 1, 2]);
+        drop(ashared);
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_eq() {
+        let a = stream::iter(1..4).shared();
+        let mut b = stream::iter(1..4).shared();
+        assert!(a != b);
+        assert_eq!(collect(a.clone()), [1, 2, 3]);
+        assert_eq!(collect(b.clone()), [1, 2, 3]);
+        assert!(a == b);
+        assert_eq!(block_on(b.next()), Some(1));
+        assert!(a != b);
+        assert!(a != stream::iter(1..4).shared());
+
+        let complete = super::Shared::from_complete(vec![1, 2, 3]);
+        assert!(complete == super::Shared::from_complete(vec![1, 2, 3]));
+        assert!(complete != super::Shared::from_complete(vec![1, 2, 4]));
+
+        let running = stream::iter(0..3).ashared();
+        let mut clone = running.clone();
+        assert!(running == clone);
+        assert_eq!(block_on(clone.next()), Some(0));
+        assert!(running != clone);
+        assert!(running != stream::iter(0..3).ashared());
+    }
+
+    #[test]
+    fn test_concurrent_catch_up() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::{Arc, Barrier};
+        use std::thread;
+
+        const ITEMS: usize = 1000;
+        // several rounds, since handles rarely race for the end of the stream
+        for _ in 0..20 {
+            let polls = Arc::new(AtomicUsize::new(0));
+            let ashared = stream::poll_fn({
+                let polls = Arc::clone(&polls);
+                move |_| {
+                    let i = polls.fetch_add(1, Ordering::SeqCst);
+                    Poll::Ready((i < ITEMS).then_some(i))
+                }
+            })
+            .ashared();
+            let started = Arc::new(Barrier::new(8));
+            let threads: Vec<_> = (0..8)
+                .map(|_| {
+                    let ashared = ashared.clone();
+                    let started = Arc::clone(&started);
+                    thread::spawn(move || {
+                        let _ = started.wait();
+                        collect(ashared)
+                    })
+                })
+                .collect();
+            drop(ashared);
+            for thread in threads {
+                assert_eq!(thread.join().unwrap(), (0..ITEMS).collect::<Vec<_>>());
+            }
+            // no handle polls the underlying stream after another one has produced its item
+            assert_eq!(polls.load(Ordering::SeqCst), ITEMS + 1);
+        }
+    }
+
+    #[test]
+    fn test_try_poll_next() {
+        use super::WouldBlock;
+        use futures::task::noop_waker_ref;
+
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut ashared = stream::iter(0..3).ashared();
+        let clone = ashared.clone();
+        let guard = clone.inner.write();
+        assert_eq!(
+            ashared.try_poll_next(&mut cx),
+            Poll::Ready(Some(Err(WouldBlock)))
+        );
+        drop(guard);
+        assert_eq!(ashared.try_poll_next(&mut cx), Poll::Ready(Some(Ok(0))));
+
+        // buffered items only need the lock to be shared
+        let mut clone = clone;
+        let guard = ashared.inner.read();
+        assert_eq!(clone.try_poll_next(&mut cx), Poll::Ready(Some(Ok(0))));
+        assert_eq!(
+            clone.try_poll_next(&mut cx),
+            Poll::Ready(Some(Err(WouldBlock)))
+        );
+        drop(guard);
+        assert_eq!(clone.try_poll_next(&mut cx), Poll::Ready(Some(Ok(1))));
+        assert_eq!(clone.try_poll_next(&mut cx), Poll::Ready(Some(Ok(2))));
+        assert_eq!(clone.try_poll_next(&mut cx), Poll::Ready(None));
+        assert_eq!(collect(ashared), [1, 2]);
+    }
+
+    #[test]
+    fn test_ashared_fair() {
+        use std::sync::{Arc, Barrier, Mutex, OnceLock};
+        use std::thread;
+
+        let drivers = Arc::new(Mutex::new(Vec::new()));
+        let started = Arc::new(Barrier::new(2));
+        let fairness = Arc::new(OnceLock::<Arc<super::Fairness>>::new());
+        let source = {
+            let drivers = Arc::clone(&drivers);
+            let started = Arc::clone(&started);
+            let fairness = Arc::clone(&fairness);
+            stream::iter(0..4).map(move |v| {
+                if v == 1 {
+                    // let the other thread start polling its handle in the meantime, and wait
+                    // until it is being polled next to this one
+                    let _ = started.wait();
+                    while fairness.get().unwrap().polling() < 2 {
+                        thread::yield_now();
+                    }
+                }
+                drivers.lock().unwrap().push(thread::current().id());
+                v
+            })
+        };
+        let mut a = source.ashared_fair();
+        let _ = fairness.set(Arc::clone(a.inner.read().fairness.as_ref().unwrap()));
+        assert_eq!(block_on(a.next()), Some(0));
+        let mut b = a.clone();
+        b.seek(2);
+        let other = thread::spawn(move || {
+            let _ = started.wait();
+            (thread::current().id(), block_on(b.next()))
+        });
+        assert_eq!(block_on(a.next()), Some(1));
+        // `a` polled the underlying stream last, so it leaves the next item to `b`
+        assert_eq!(block_on(a.next()), Some(2));
+        let (b_id, b_item) = other.join().unwrap();
+        assert_eq!(b_item, Some(2));
+        let drivers = drivers.lock().unwrap();
+        assert_eq!(drivers[1], thread::current().id());
+        assert_eq!(drivers[2], b_id);
+        drop(drivers);
+        assert_eq!(collect(a), [3]);
+    }
+
+    #[test]
+    fn test_shared_with_drop() {
+        use core::cell::Cell;
+
+        let dropped = Cell::new(0);
+        let shared = stream::iter(0..3).shared_with_drop(|| dropped.set(dropped.get() + 1));
+        let clone = shared.clone();
+        assert_eq!(collect(shared), [0, 1, 2]);
+        assert_eq!(dropped.get(), 1);
+        drop(clone);
+        assert_eq!(dropped.get(), 1);
+
+        let dropped = Cell::new(0);
+        let mut shared = stream::iter(0..3).shared_with_drop(|| dropped.set(dropped.get() + 1));
+        let clone = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(0));
+        drop(shared);
+        assert_eq!(dropped.get(), 0);
+        drop(clone);
+        assert_eq!(dropped.get(), 1);
+    }
+
+    #[test]
+    fn test_poll_next_chunk() {
+        let mut shared = stream::iter(0..10).shared();
+        let clone = shared.clone();
+        let mut next_chunk = |max| block_on(future::poll_fn(|cx| shared.poll_next_chunk(cx, max)));
+        assert_eq!(next_chunk(3), [0]);
+        assert_eq!(collect(clone), (0..10).collect::<Vec<_>>());
+        assert_eq!(next_chunk(3), [1, 2, 3]);
+        assert_eq!(next_chunk(3), [4, 5, 6]);
+        assert_eq!(next_chunk(5), [7, 8, 9]);
+        assert_eq!(next_chunk(5), []);
+    }
+
+    #[test]
+    fn test_drain_ready() {
+        use core::cell::Cell;
+
+        let produced = Cell::new(0);
+        let mut shared = stream::iter(0..5)
+            .inspect(|_| produced.set(produced.get() + 1))
+            .shared();
+        let mut clone = shared.clone();
+        assert_eq!(shared.drain_ready(), []);
+        assert_eq!(block_on(clone.next()), Some(0));
+        assert_eq!(block_on(clone.next()), Some(1));
+        assert_eq!(shared.drain_ready(), [0, 1]);
+        assert_eq!(shared.drain_ready(), []);
+        assert_eq!(produced.get(), 2);
+        assert_eq!(collect(shared), [2, 3, 4]);
+        assert_eq!(clone.drain_ready(), [2, 3, 4]);
+    }
+
+    #[test]
+    fn test_shared_map() {
+        use core::cell::Cell;
+
+        let calls = Cell::new(0);
+        let shared = stream::iter(1..4).shared_map(|v| {
+            calls.set(calls.get() + 1);
+            v * 10
+        });
+        let clones = [shared.clone(), shared.clone()];
+        assert_eq!(collect(shared), [10, 20, 30]);
+        for clone in clones {
+            assert_eq!(collect(clone), [10, 20, 30]);
+        }
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn test_items_produced() {
+        let mut shared = stream::iter(0..6).shared_replay_last(2);
+        assert_eq!(shared.items_produced(), 0);
+        let mut produced = Vec::new();
+        while block_on(shared.next()).is_some() {
+            produced.push(shared.items_produced());
+        }
+        assert_eq!(produced, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(shared.buffered_len(), 2);
+        assert_eq!(shared.items_produced(), 6);
+
+        let ashared = stream::iter(0..3).ashared();
+        assert_eq!(collect(ashared.clone()).len(), 3);
+        ashared.reset_all();
+        assert_eq!(ashared.items_produced(), 3);
+        assert_eq!(
+            super::Ashared::from_complete(vec![1, 2]).items_produced(),
+            2
+        );
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_ashared_local() {
+        use std::rc::Rc;
+        use std::thread;
+
+        let not_send = Rc::new(());
+        let source = stream::iter(0..3).map(move |v| {
+            let _ = &not_send;
+            v
+        });
+        let ashared = source.ashared_local();
+        let reader = ashared.clone();
+        let mut lagging = ashared.clone();
+        assert_eq!(block_on(lagging.next()), Some(0));
+        let early = ashared.clone();
+        assert_eq!(collect(ashared), [0, 1, 2]);
+        assert_eq!(
+            thread::spawn(move || collect(reader)).join().unwrap(),
+            [0, 1, 2]
+        );
+        // the underlying stream has been dropped on this thread once it finished
+        assert!(early.upstream_finished());
+
+        // the size hint of the underlying stream is unknown on other threads
+        let ashared = stream::iter(0..3).ashared_local();
+        let clone = ashared.clone();
+        assert_eq!(
+            thread::spawn(move || clone.size_hint()).join().unwrap(),
+            (0, None)
+        );
+        assert_eq!(ashared.size_hint(), (3, Some(3)));
+
+        let mut ashared = stream::iter(0..3).ashared_local();
+        let mut other = ashared.clone();
+        assert_eq!(block_on(ashared.next()), Some(0));
+        let polled_off_thread = thread::spawn(move || {
+            assert_eq!(block_on(other.next()), Some(0));
+            let _ = block_on(other.next());
+        })
+        .join();
+        assert!(polled_off_thread.is_err());
+        assert_eq!(collect(ashared), [1, 2]);
+    }
+
+    #[test]
+    fn test_shared_take_while() {
+        use core::cell::Cell;
+
+        let calls = Cell::new(0);
+        let shared = stream::iter([1, 2, 5, 3, 4]).shared_take_while(|&v| {
+            calls.set(calls.get() + 1);
+            v < 4
+        });
+        let clone = shared.clone();
+        assert_eq!(collect(shared), [1, 2]);
+        assert_eq!(collect(clone.clone()), [1, 2]);
+        assert_eq!(calls.get(), 3);
+        assert!(clone.upstream_finished());
+        assert_eq!(clone.size_hint(), (2, Some(2)));
+    }
+
+    #[test]
+    fn test_shared_scan() {
+        use core::cell::Cell;
+
+        let calls = Cell::new(0);
+        // running sums, until they exceed 10
+        let shared = stream::iter(1..10).shared_scan(0, |sum, v| {
+            calls.set(calls.get() + 1);
+            *sum += v;
+            (*sum <= 10).then(|| sum.to_string())
+        });
+        let clones = [shared.clone(), shared.clone()];
+        assert_eq!(collect(shared), ["1", "3", "6", "10"]);
+        for clone in clones {
+            assert_eq!(collect(clone), ["1", "3", "6", "10"]);
+        }
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn test_shared_filter() {
+        use core::cell::Cell;
+
+        let calls = Cell::new(0);
+        let shared = stream::iter(0..10).shared_filter(|&v| {
+            calls.set(calls.get() + 1);
+            v % 3 == 0
+        });
+        let clones = [shared.clone(), shared.clone(), shared.clone()];
+        assert_eq!(shared.size_hint(), (0, Some(10)));
+        assert_eq!(collect(shared), [0, 3, 6, 9]);
+        for clone in clones {
+            assert_eq!(clone.buffered_len(), 4);
+            assert_eq!(collect(clone), [0, 3, 6, 9]);
+        }
+        assert_eq!(calls.get(), 10);
+    }
+
+    #[test]
+    fn test_hash() {
+        use std::collections::hash_map::DefaultHasher;
+        use std::hash::{Hash, Hasher};
+
+        fn hash<T: Hash>(value: &T) -> u64 {
+            let mut hasher = DefaultHasher::new();
+            value.hash(&mut hasher);
+            hasher.finish()
+        }
+
+        let a = stream::iter(1..4).shared();
+        let b = stream::iter(1..4).shared();
+        assert_ne!(hash(&a), hash(&b));
+        assert_eq!(hash(&a), hash(&a.clone()));
+        assert_eq!(collect(a.clone()), [1, 2, 3]);
+        assert_eq!(collect(b.clone()), [1, 2, 3]);
+        assert!(a == b);
+        assert_eq!(hash(&a), hash(&b));
+        let c = super::Shared::from_complete(vec![1, 2, 4]);
+        assert_ne!(hash(&c), hash(&super::Shared::from_complete(vec![1, 2, 3])));
     }
 
     #[test]
-    fn test_everything_ashared() {
-        let seen = RwLock::new(vec![]);
-        let orig_stream = stream::iter(["a", "b", "c"].iter().map(|v| (*v).to_string()))
-            .inspect(|v| {
-                seen.write().unwrap().push(v.clone());
-            })
-            .ashared();
-        test_everything(orig_stream, || seen.read().unwrap().clone());
+    fn test_shared_split() {
+        use core::cell::Cell;
+
+        let produced = Cell::new(0);
+        let (oks, errs) = stream::iter([Ok(1), Err('a'), Ok(2), Ok(3), Err('b')])
+            .inspect(|_| produced.set(produced.get() + 1))
+            .shared_split();
+        assert_eq!(collect(oks.clone()), [1, 2, 3]);
+        assert_eq!(collect(errs.clone()), ['a', 'b']);
+        assert_eq!(collect(oks.clone()), [1, 2, 3]);
+        assert_eq!(produced.get(), 5);
+        assert_eq!(oks.buffered_len(), 3);
+        assert_eq!(errs.buffered_len(), 2);
     }
 
     #[test]
-    fn test_size_hint_for_unfinished() {
-        let mut stream = stream::iter(["a", "b", "c"].iter().map(|v| (*v).to_string())).shared();
-        assert_eq!(stream.size_hint(), (3, Some(3)));
-        assert!(!stream.is_terminated());
-        assert_eq!(block_on(stream.next()), Some("a".to_string()));
-        assert_eq!(stream.size_hint(), (2, Some(2)));
-        assert!(!stream.is_terminated());
+    fn test_position() {
+        let mut shared = stream::iter(0..5).shared_replay_last(2);
+        let clone = shared.clone();
+        assert_eq!(shared.position(), 0);
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(shared.position(), 1);
+        assert_eq!(shared.size_hint(), (4, Some(4)));
+        assert_eq!(collect(shared.clone().take(3)), [1, 2, 3]);
+        // the items before 2 have been dropped
+        assert_eq!(clone.position(), 2);
+        assert_eq!(clone.size_hint(), (3, Some(3)));
+        shared.reset_all();
+        assert_eq!(shared.position(), 0);
+
+        let mut ashared = stream::iter(0..5).ashared();
+        ashared.seek(3);
+        assert_eq!(ashared.position(), 3);
+    }
+
+    #[test]
+    fn test_rewind() {
+        let mut shared = stream::iter(1..=3).shared();
+        assert_eq!(collect(&mut shared), [1, 2, 3]);
+        shared.rewind();
+        assert!(!shared.is_terminated());
+        assert_eq!(collect(&mut shared), [1, 2, 3]);
+
+        let mut ashared = stream::iter(1..=3).ashared();
+        assert_eq!(block_on(ashared.next()), Some(1));
+        ashared.rewind();
+        assert_eq!(collect(ashared), [1, 2, 3]);
+    }
+
+    #[test]
+    fn test_forks() {
+        let mut shared = stream::iter(0..5).shared();
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(block_on(shared.next()), Some(1));
+        let clone = shared.clone();
+        let here = shared.fork_here();
+        let start = shared.fork_from_start();
+        let live = shared.subscribe_live();
+        assert_eq!(clone.position(), 2);
+        assert_eq!(here.position(), 2);
+        assert_eq!(start.position(), 0);
+        assert_eq!(live.position(), 2);
+        assert_eq!(collect(here), [2, 3, 4]);
+        assert_eq!(collect(start), [0, 1, 2, 3, 4]);
+        assert_eq!(collect(clone), [2, 3, 4]);
+        assert_eq!(collect(live), [2, 3, 4]);
+
+        // dropped items are skipped
+        let mut shared = stream::iter(0..5).shared_replay_last(2);
+        assert_eq!(collect(&mut shared), [0, 1, 2, 3, 4]);
+        assert_eq!(collect(shared.fork_from_start()), [3, 4]);
+        assert_eq!(collect(shared.fork_here()), []);
+    }
+
+    #[test]
+    fn test_clone_at() {
+        let shared = stream::iter(0..5).shared();
+        assert_eq!(collect(shared.clone_at(2)), [2, 3, 4]);
+        assert_eq!(collect(shared.clone_at(7)), []);
+        assert_eq!(collect(shared.clone_at(0)), [0, 1, 2, 3, 4]);
+
+        // the position of a new handle is kept for reclaiming right away
+        let mut ashared = stream::iter(0..5).ashared_reclaiming();
+        let clone = ashared.clone_at(1);
+        assert_eq!(collect(&mut ashared), [0, 1, 2, 3, 4]);
+        assert_eq!(collect(clone), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_debug() {
+        // neither the stream nor its items implement `Debug`
+        struct Opaque;
+
+        #[derive(Debug)]
+        struct Holder {
+            shared: super::Shared<stream::Iter<std::vec::IntoIter<Opaque>>>,
+        }
+
+        let holder = Holder {
+            shared: stream::iter(vec![Opaque, Opaque]).shared_with(|_| Opaque),
+        };
+        assert_eq!(
+            format!("{holder:?}"),
+            "Holder { shared: Shared { idx: 0, buffered: 0, finished: false, .. } }"
+        );
+        let mut shared = holder.shared;
+        assert!(block_on(shared.next()).is_some());
+        assert_eq!(
+            format!("{shared:?}"),
+            "Shared { idx: 1, buffered: 1, finished: false, .. }"
+        );
+        assert_eq!(
+            format!("{:?}", super::SharedHandle::from(shared)),
+            "Local(Shared { idx: 1, buffered: 1, finished: false, .. })"
+        );
+
+        let shared = stream::iter(0..2).shared();
+        let _ = collect(shared.clone());
+        let state = format!("{:?}", shared.debug_state());
+        assert!(state.starts_with("Shared { inner: RefCell { value: InnerState {"));
+        assert!(state.contains("values: [0, 1]"));
+    }
+
+    #[test]
+    fn test_seek() {
+        let mut shared = stream::iter(0..10).shared();
+        let clone = shared.clone();
+        shared.seek(3);
+        assert_eq!(shared.size_hint(), (7, Some(7)));
+        assert_eq!(block_on(shared.next()), Some(3));
+        assert_eq!(clone.size_hint(), (10, Some(10)));
+        shared.seek(1);
+        assert_eq!(collect(shared.clone().take(3)), [1, 2, 3]);
+        shared.seek(8);
+        assert_eq!(collect(&mut shared), [8, 9]);
+        shared.seek(0);
+        assert_eq!(collect(&mut shared), (0..10).collect::<Vec<_>>());
+        shared.seek(20);
+        assert_eq!(shared.size_hint(), (0, Some(0)));
+        assert_eq!(block_on(shared.next()), None);
+        assert_eq!(collect(clone), (0..10).collect::<Vec<_>>());
+
+        // handles can't seek to dropped items
+        let mut ashared = stream::iter(0..10).ashared_reclaiming();
+        ashared.seek(5);
+        assert_eq!(ashared.size_hint(), (5, Some(5)));
+        assert_eq!(block_on(ashared.next()), Some(5));
+        ashared.seek(12);
+        assert_eq!(block_on(ashared.next()), None);
+        ashared.seek(0);
+        assert_eq!(collect(ashared), [9]);
+    }
+
+    #[test]
+    fn test_into_inner() {
+        let mut shared = stream::iter(1..=4).shared();
+        let clone = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(1));
+        let shared = shared.into_inner().unwrap_err();
+        drop(clone);
+        let stream = shared.into_inner().unwrap();
+
+        // the buffered item would be lost
+        let clone = stream.shared();
+        let mut shared = clone.clone();
+        assert_eq!(block_on(shared.next()), Some(2));
+        drop(shared);
+        let mut clone = clone.into_inner().unwrap_err();
+        assert_eq!(block_on(clone.next()), Some(2));
+        let mut stream = clone.into_inner().unwrap();
+        assert_eq!(block_on(stream.next()), Some(3));
+
+        let mut clone = stream.shared();
+        assert_eq!(collect(&mut clone), [4]);
+        assert!(clone.into_inner().is_err());
+
+        let ashared = stream::iter(1..=4).ashared();
+        assert_eq!(collect(ashared.into_inner().unwrap()), [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_subscribe_live() {
+        let mut shared = stream::iter(1..=4).shared();
+        let replaying = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(block_on(shared.next()), Some(2));
+        let live = replaying.subscribe_live();
+        assert_eq!(live.size_hint(), (2, Some(2)));
+        assert_eq!(collect(live), [3, 4]);
+        assert_eq!(collect(replaying), [1, 2, 3, 4]);
+        assert!(shared.subscribe_live().is_terminated());
+        assert_eq!(collect(shared.subscribe_live()), []);
+
+        let ashared = stream::iter(1..=4).ashared();
+        assert_eq!(collect(ashared.subscribe_live()), [1, 2, 3, 4]);
+        assert_eq!(collect(ashared.subscribe_live()), []);
+    }
+
+    #[test]
+    fn test_shared_by_ref() {
+        let mut source = stream::iter(0..5);
+        {
+            let shared = source.shared_by_ref();
+            let clone = shared.clone();
+            assert_eq!(collect(shared.take(2)), [0, 1]);
+            assert_eq!(collect(clone.take(3)), [0, 1, 2]);
+        }
+        assert_eq!(collect(&mut source), [3, 4]);
+        assert_eq!(collect(source.shared_by_ref()), []);
+    }
+
+    #[test]
+    fn test_shared_boxed() {
+        let shared = vec![
+            stream::iter(0..3).shared_boxed(),
+            stream::once(future::ready(7)).shared_boxed(),
+            stream::iter(vec![4, 5]).map(|v| v * 2).shared_boxed(),
+        ];
+        let clones = shared.clone();
+        for (shared, expected) in shared
+            .into_iter()
+            .zip([vec![0, 1, 2], vec![7], vec![8, 10]])
+        {
+            assert_eq!(collect(shared), expected);
+        }
+        assert_eq!(collect(stream::iter(clones).flatten()), [0, 1, 2, 7, 8, 10]);
+
+        let ashared = vec![
+            stream::iter(0..3).ashared_boxed(),
+            stream::iter(vec![4, 5]).map(|v| v * 2).ashared_boxed(),
+        ];
+        let handle =
+            std::thread::spawn(move || ashared.into_iter().map(collect).collect::<Vec<_>>());
+        assert_eq!(handle.join().unwrap(), [vec![0, 1, 2], vec![8, 10]]);
+    }
+
+    #[test]
+    fn test_is_sole_owner() {
+        let shared = stream::iter(1..=3).shared();
+        assert!(shared.is_sole_owner());
+        let weak = shared.downgrade();
+        assert!(shared.is_sole_owner());
+        let clone = weak.upgrade().unwrap();
+        assert!(!shared.is_sole_owner());
+        drop(clone);
+        assert!(shared.is_sole_owner());
+
+        let ashared = stream::iter(1..=3).ashared();
+        let clone = ashared.clone();
+        assert!(!ashared.is_sole_owner());
+        drop(ashared);
+        assert!(clone.is_sole_owner());
+    }
+
+    #[test]
+    fn test_clone_count() {
+        let shared = stream::iter(1..=3).shared();
+        assert_eq!(shared.clone_count(), 1);
+        let clone = shared.clone();
+        let windows = shared.clone().windows(2);
+        assert_eq!(shared.clone_count(), 3);
+        drop(clone);
+        assert_eq!(shared.clone_count(), 2);
+        drop(windows);
+        assert_eq!(shared.clone_count(), 1);
+
+        let ashared = stream::iter(1..=3).ashared();
+        let clone = ashared.clone();
+        assert_eq!(clone.clone_count(), 2);
+        drop(ashared);
+        assert_eq!(clone.clone_count(), 1);
+    }
+
+    #[test]
+    fn test_peek() {
+        let mut shared = stream::iter(1..=2).shared_tracking();
+        let mut clone = shared.clone();
+        assert_eq!(block_on(shared.peek()), Some(1));
+        assert_eq!(block_on(shared.peek()), Some(1));
+        assert_eq!(shared.consumer_positions(), [0, 0]);
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(block_on(clone.peek()), Some(1));
+        assert_eq!(collect(&mut shared), [2]);
+        assert_eq!(block_on(shared.peek()), None);
+        assert_eq!(collect(clone), [1, 2]);
+
+        let mut ashared = stream::iter(1..=2).ashared();
+        assert_eq!(block_on(ashared.peek()), Some(1));
+        assert_eq!(collect(ashared), [1, 2]);
+    }
+
+    #[test]
+    fn test_from_complete() {
+        let shared = super::Shared::from_complete(vec![1, 2, 3]);
+        let mut clone = shared.clone();
+        assert!(!clone.is_terminated());
+        assert_eq!(clone.size_hint(), (3, Some(3)));
+        assert_eq!(collect(shared), [1, 2, 3]);
+        assert_eq!(block_on((&mut clone).collect::<Vec<_>>()), [1, 2, 3]);
+        assert!(clone.is_terminated());
+
+        let ashared: super::Ashared<_> = vec!['a', 'b'].into();
+        assert_eq!(collect(ashared), ['a', 'b']);
+        let shared: super::Shared<_> = (0..4).collect();
+        assert_eq!(collect(shared), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_empty() {
+        let shared: super::Shared<stream::Empty<u8>> = super::Shared::empty();
+        assert!(shared.is_terminated());
+        assert_eq!(collect(shared), []);
+        let ashared: super::Ashared<stream::Empty<u8>> = super::Ashared::default();
+        assert!(ashared.is_terminated());
+        assert_eq!(collect(ashared), []);
+    }
+
+    #[test]
+    fn test_finished_iter() {
+        let mut shared = stream::iter(1..=4).shared();
+        assert_eq!(block_on(shared.next()), Some(1));
+        let ashared = stream::iter(1..=4).ashared();
+        assert!(shared.clone().finished_iter().is_none());
+        assert!(ashared.clone().finished_iter().is_none());
+
+        let clone = shared.clone();
+        assert_eq!(block_on(shared.next()), Some(2));
+        assert_eq!(collect(shared.clone()), [3, 4]);
+        assert_eq!(
+            clone.finished_iter().unwrap().collect::<Vec<_>>(),
+            [2, 3, 4]
+        );
+        assert_eq!(shared.finished_iter().unwrap().collect::<Vec<_>>(), [3, 4]);
+
+        assert_eq!(collect(ashared.clone()), [1, 2, 3, 4]);
+        assert_eq!(
+            ashared.finished_iter().unwrap().collect::<Vec<_>>(),
+            [1, 2, 3, 4]
+        );
+    }
+
+    #[test]
+    fn test_shared_handle() {
+        use super::{Local, SharedHandle, ThreadSafe};
+
+        let mut local = stream::iter(1..=3).shared_with_mode::<Local>();
+        assert!(matches!(local, SharedHandle::Local(_)));
+        assert_eq!(block_on(local.next()), Some(1));
+        let clone = local.clone();
+        assert_eq!(clone.size_hint(), (2, Some(2)));
+        assert_eq!(collect(clone), [2, 3]);
+        assert_eq!(collect(&mut local), [2, 3]);
+        assert!(local.is_terminated());
+
+        let thread_safe = stream::iter(1..=3).shared_with_mode::<ThreadSafe>();
+        assert!(matches!(thread_safe, SharedHandle::ThreadSafe(_)));
+        assert_eq!(collect(thread_safe.clone()), [1, 2, 3]);
+        assert_eq!(collect(thread_safe), [1, 2, 3]);
+
+        let handle: SharedHandle<_> = stream::iter(1..=3).shared().into();
+        assert_eq!(collect(handle), [1, 2, 3]);
+    }
+
+    #[cfg(feature = "sink")]
+    #[test]
+    fn test_sink() {
+        use super::SinkError;
+        use core::pin::Pin;
+        use futures::channel::mpsc::{unbounded, SendError, UnboundedReceiver, UnboundedSender};
+        use futures::sink::{Sink, SinkExt};
+
+        /// Yields the items sent into it.
+        struct Loopback(UnboundedSender<i32>, UnboundedReceiver<i32>);
+
+        impl Stream for Loopback {
+            type Item = i32;
+            fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<i32>> {
+                self.1.poll_next_unpin(cx)
+            }
+        }
+
+        impl Sink<i32> for Loopback {
+            type Error = SendError;
+            fn poll_ready(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Result<(), SendError>> {
+                Pin::new(&mut self.0).poll_ready(cx)
+            }
+            fn start_send(mut self: Pin<&mut Self>, item: i32) -> Result<(), SendError> {
+                Pin::new(&mut self.0).start_send(item)
+            }
+            fn poll_flush(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Result<(), SendError>> {
+                Pin::new(&mut self.0).poll_flush(cx)
+            }
+            fn poll_close(
+                mut self: Pin<&mut Self>,
+                cx: &mut Context<'_>,
+            ) -> Poll<Result<(), SendError>> {
+                Pin::new(&mut self.0).poll_close(cx)
+            }
+        }
+
+        let (sender, receiver) = unbounded();
+        let mut shared = Loopback(sender, receiver).shared();
+        let mut clone = shared.clone();
+        block_on(shared.send(1)).unwrap();
+        block_on(shared.send(2)).unwrap();
+        assert_eq!(block_on(clone.send(3)), Err(SinkError::Busy));
+        assert_eq!(block_on(clone.next()), Some(1));
+        block_on(shared.close()).unwrap();
+
+        // dropping the handle using the sink frees it up for the others
+        drop(shared);
+        assert!(matches!(block_on(clone.send(3)), Err(SinkError::Sink(_))));
+        assert_eq!(collect(clone.clone()), [2]);
+        assert_eq!(block_on(clone.send(3)), Err(SinkError::Finished));
+    }
+
+    #[cfg(feature = "io")]
+    #[test]
+    fn test_into_async_read() {
+        use futures::io::AsyncReadExt;
+
+        let shared = stream::iter([&b"hello "[..], b"shared ", b"world"]).shared();
+        let mut a = shared.clone().into_async_read();
+        let mut b = shared.into_async_read();
+        let mut buf = [0; 4];
+        assert_eq!(block_on(a.read(&mut buf)).unwrap(), 4);
+        assert_eq!(&buf, b"hell");
+        // reads don't span items
+        assert_eq!(block_on(a.read(&mut buf)).unwrap(), 2);
+        assert_eq!(&buf[..2], b"o ");
+        let mut rest = Vec::new();
+        assert_eq!(block_on(a.read_to_end(&mut rest)).unwrap(), 12);
+        assert_eq!(rest, b"shared world");
+        let mut all = Vec::new();
+        assert_eq!(block_on(b.read_to_end(&mut all)).unwrap(), 18);
+        assert_eq!(all, b"hello shared world");
+        assert_eq!(block_on(b.read(&mut buf)).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_unpin() {
+        fn assert_unpin<T: Unpin>(_: &T) {}
+
+        // `unfold` holds a future that isn't `Unpin`
+        let unfold = stream::unfold(0, |i| async move { (i < 3).then_some((i, i + 1)) });
+        let mut shared = unfold.shared();
+        assert_unpin(&shared);
+        assert_eq!(block_on(shared.next()), Some(0));
+        let mut moved = Box::new(shared);
+        assert_eq!(block_on(moved.next()), Some(1));
+
+        let unfold = stream::unfold(0, |i| async move { (i < 3).then_some((i, i + 1)) });
+        let mut ashared = unfold.ashared();
+        assert_unpin(&ashared);
+        assert_eq!(block_on(ashared.next()), Some(0));
     }
 
     #[test]
@@ -513,4 +4042,518 @@ mod test {
         assert!(pool.try_run_one());
         assert!(pool.try_run_one());
     }
+
+    #[test]
+    #[should_panic(expected = "shared stream has more items than positions fit in a usize")]
+    fn test_position_overflow() {
+        let mut shared = stream::iter(0..5).shared_reclaiming();
+        shared.inner.borrow_mut().reclaimed = usize::MAX - 2;
+        shared.seek(usize::MAX - 2);
+        assert_eq!(block_on(shared.next()), Some(0));
+        assert_eq!(block_on(shared.next()), Some(1));
+        assert_eq!(shared.position(), usize::MAX);
+        let _ = block_on(shared.next());
+    }
+
+    #[test]
+    fn test_split_round_robin() {
+        let [evens, odds]: [_; 2] = stream::iter(0..7)
+            .shared()
+            .split_round_robin(2)
+            .try_into()
+            .unwrap();
+        assert_eq!(evens.size_hint(), (4, Some(4)));
+        assert_eq!(odds.size_hint(), (3, Some(3)));
+        assert_eq!(collect(odds), [1, 3, 5]);
+        assert_eq!(collect(evens), [0, 2, 4, 6]);
+
+        // starting at the position of the handle, with the streams polled in turns
+        let mut shared = stream::iter(0..10).shared();
+        assert_eq!(block_on(shared.next()), Some(0));
+        let mut streams = shared.split_round_robin(3);
+        let mut items = vec![];
+        for _ in 0..3 {
+            for stream in &mut streams {
+                items.extend(block_on(stream.next()));
+            }
+        }
+        assert_eq!(items, (1..10).collect::<Vec<_>>());
+        assert!(streams
+            .iter_mut()
+            .all(|stream| block_on(stream.next()).is_none()));
+        assert!(streams.iter().all(FusedStream::is_terminated));
+    }
+
+    #[test]
+    fn test_prefetch() {
+        use core::cell::Cell;
+        use std::rc::Rc;
+
+        let polls = Rc::new(Cell::new(0));
+        let shared = stream::iter(0..5)
+            .inspect({
+                let polls = Rc::clone(&polls);
+                move |_| polls.set(polls.get() + 1)
+            })
+            .shared();
+        block_on(shared.prefetch(3));
+        assert_eq!(polls.get(), 3);
+        assert_eq!(shared.buffered_len(), 3);
+        assert_eq!(shared.position(), 0);
+        assert_eq!(collect(shared.clone().take(3)), [0, 1, 2]);
+        assert_eq!(polls.get(), 3);
+        block_on(shared.prefetch(2));
+        assert_eq!(polls.get(), 3);
+
+        // the stream ends before reaching `upto`
+        block_on(shared.prefetch(10));
+        assert!(shared.upstream_finished());
+        assert_eq!(collect(shared), [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_count_future() {
+        use core::cell::Cell;
+        use std::rc::Rc;
+
+        let polls = Rc::new(Cell::new(0));
+        let shared = stream::iter(0..5)
+            .inspect({
+                let polls = Rc::clone(&polls);
+                move |_| polls.set(polls.get() + 1)
+            })
+            .shared();
+        let clone = shared.clone();
+        let first = shared.count_future();
+        let second = shared.count_future();
+        assert_eq!(block_on(first), 5);
+        assert_eq!(block_on(second), 5);
+        assert_eq!(polls.get(), 5);
+        assert_eq!(collect(clone), [0, 1, 2, 3, 4]);
+        assert_eq!(polls.get(), 5);
+
+        // items dropped from the buffer count as well
+        let mut shared = stream::iter(0..5).shared_reclaiming();
+        assert_eq!(block_on((&mut shared).take(3).count()), 3);
+        assert_eq!(block_on(shared.count_future()), 5);
+    }
+
+    #[test]
+    fn test_pump() {
+        let mut pool = LocalPool::new();
+        let spawner = pool.spawner();
+
+        let (mut sender, receiver) = channel(2);
+        let shared = receiver.shared();
+        let clone = shared.clone();
+        spawner.spawn_local(shared.pump()).unwrap();
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+        pool.run_until_stalled();
+        // buffered without polling any handle
+        assert_eq!(clone.buffered_len(), 2);
+        drop(sender);
+        pool.run();
+        assert_eq!(clone.clone_count(), 1);
+        assert!(clone.inner.borrow().stream.is_none());
+        assert_eq!(collect(clone), [1, 2]);
+
+        // the pump doesn't count as a handle that has to catch up
+        let (mut sender, receiver) = channel(2);
+        let mut shared = receiver.shared_with_capacity(1);
+        spawner.spawn_local(shared.clone().pump()).unwrap();
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+        pool.run_until_stalled();
+        assert_eq!(shared.buffered_len(), 1);
+        assert_eq!(block_on(shared.next()), Some(1));
+        pool.run_until_stalled();
+        assert_eq!(shared.consumer_positions(), [1]);
+        assert_eq!(shared.buffered_len(), 1);
+        drop(sender);
+        assert_eq!(collect(shared), [2]);
+        pool.run();
+    }
+
+    use core::task::{Context, Poll};
+    use futures::task::{waker, ArcWake};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    /// A waker that remembers whether it has been woken.
+    struct FlagWaker(AtomicBool);
+
+    impl ArcWake for FlagWaker {
+        fn wake_by_ref(arc_self: &Arc<Self>) {
+            arc_self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    /// A consumer that is polled by hand, so tests can pick the exact order in which several
+    /// consumers make progress.
+    struct Consumer<S> {
+        stream: S,
+        waker: Arc<FlagWaker>,
+    }
+
+    impl<S: Stream + Unpin> Consumer<S> {
+        fn new(stream: S) -> Self {
+            Self {
+                stream,
+                waker: Arc::new(FlagWaker(AtomicBool::new(false))),
+            }
+        }
+
+        /// Polls the stream once with this consumer's waker.
+        fn poll(&mut self) -> Poll<Option<S::Item>> {
+            let waker = waker(Arc::clone(&self.waker));
+            self.stream
+                .poll_next_unpin(&mut Context::from_waker(&waker))
+        }
+
+        /// Returns whether the consumer has been woken since the last call.
+        fn take_woken(&self) -> bool {
+            self.waker.0.swap(false, Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_shared_channel() {
+        let (sender, shared) = super::shared_channel();
+        let mut a = Consumer::new(shared.clone());
+        assert_eq!(a.poll(), Poll::Pending);
+        sender.push(1);
+        assert!(a.take_woken());
+        assert_eq!(a.poll(), Poll::Ready(Some(1)));
+        sender.push(2);
+        sender.push(3);
+        assert_eq!(a.poll(), Poll::Ready(Some(2)));
+        assert_eq!(a.poll(), Poll::Ready(Some(3)));
+        assert_eq!(a.poll(), Poll::Pending);
+        sender.close();
+        assert!(a.take_woken());
+        assert_eq!(a.poll(), Poll::Ready(None));
+        assert_eq!(collect(shared), [1, 2, 3]);
+
+        let (sender, shared) = super::shared_channel();
+        sender.push("dropped");
+        drop(sender);
+        assert_eq!(collect(shared), ["dropped"]);
+
+        // items pushed after the end are ignored
+        let (sender, shared) = super::shared_channel();
+        sender.push(1);
+        shared.close_source();
+        sender.push(2);
+        assert_eq!(shared.buffered_len(), 1);
+        assert_eq!(collect(shared), [1]);
+    }
+
+    #[test]
+    fn test_shared_with_budget() {
+        let mut shared = Consumer::new(stream::iter(0..100).shared_with_budget(10));
+        shared.stream.seek(45);
+        for _ in 0..4 {
+            assert_eq!(shared.poll(), Poll::Pending);
+            assert!(shared.take_woken());
+        }
+        assert_eq!(shared.poll(), Poll::Ready(Some(45)));
+        assert_eq!(shared.stream.buffered_len(), 46);
+        assert_eq!(shared.poll(), Poll::Ready(Some(46)));
+
+        let waker = waker(Arc::new(FlagWaker(AtomicBool::new(false))));
+        let mut cx = Context::from_waker(&waker);
+        let mut polls = 1;
+        while shared.stream.poll_until_finished(&mut cx).is_pending() {
+            polls += 1;
+        }
+        assert_eq!(polls, 6);
+        assert_eq!(collect(shared.stream), (47..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_close_source() {
+        let (mut sender, receiver) = channel(4);
+        let mut a = Consumer::new(receiver.shared());
+        let mut b = Consumer::new(a.stream.clone());
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+        assert_eq!(a.poll(), Poll::Ready(Some(1)));
+        assert_eq!(a.poll(), Poll::Ready(Some(2)));
+        assert_eq!(a.poll(), Poll::Pending);
+
+        b.stream.close_source();
+        assert!(a.take_woken());
+        // the underlying stream has been dropped
+        assert!(sender.try_send(3).unwrap_err().is_disconnected());
+        assert_eq!(a.poll(), Poll::Ready(None));
+        assert_eq!(b.poll(), Poll::Ready(Some(1)));
+        assert_eq!(b.poll(), Poll::Ready(Some(2)));
+        assert_eq!(b.poll(), Poll::Ready(None));
+        assert!(b.stream.is_terminated());
+    }
+
+    #[test]
+    fn test_shared_with_capacity() {
+        let mut fast = Consumer::new(stream::iter(0..10).shared_with_capacity(3));
+        let mut slow = Consumer::new(fast.stream.clone());
+        for i in 0..3 {
+            assert_eq!(fast.poll(), Poll::Ready(Some(i)));
+        }
+        assert_eq!(fast.poll(), Poll::Pending);
+        assert_eq!(fast.stream.inner.borrow().values.len(), 3);
+
+        assert_eq!(slow.poll(), Poll::Ready(Some(0)));
+        assert!(fast.take_woken());
+        assert_eq!(fast.poll(), Poll::Ready(Some(3)));
+        assert_eq!(fast.poll(), Poll::Pending);
+
+        // dropping the laggard releases the backpressure
+        let slow = slow.stream;
+        drop(slow);
+        assert!(fast.take_woken());
+        assert_eq!(collect(&mut fast.stream), (4..10).collect::<Vec<_>>());
+        assert!(fast.stream.inner.borrow().values.len() <= 3);
+
+        let mut a = Consumer::new(stream::iter(0..10).ashared_with_capacity(1));
+        let mut b = Consumer::new(a.stream.clone());
+        for i in 0..10 {
+            assert_eq!(a.poll(), Poll::Ready(Some(i)));
+            assert_eq!(a.poll(), Poll::Pending);
+            assert_eq!(b.poll(), Poll::Ready(Some(i)));
+            assert!(a.take_woken());
+        }
+        assert_eq!(a.poll(), Poll::Ready(None));
+        assert_eq!(b.poll(), Poll::Ready(None));
+    }
+
+    #[test]
+    fn test_set_capacity() {
+        let mut fast = Consumer::new(stream::iter(0..10).shared_with_capacity(2));
+        let mut slow = Consumer::new(fast.stream.clone());
+        assert_eq!(fast.poll(), Poll::Ready(Some(0)));
+        assert_eq!(fast.poll(), Poll::Ready(Some(1)));
+        assert_eq!(fast.poll(), Poll::Pending);
+
+        fast.stream.set_capacity(4);
+        assert!(fast.take_woken());
+        assert_eq!(fast.poll(), Poll::Ready(Some(2)));
+        assert_eq!(fast.poll(), Poll::Ready(Some(3)));
+        assert_eq!(fast.poll(), Poll::Pending);
+
+        // unread items are kept, and the fast handle waits until the slow one has caught up
+        assert_eq!(slow.poll(), Poll::Ready(Some(0)));
+        slow.stream.set_capacity(1);
+        assert_eq!(fast.stream.buffered_len(), 3);
+        assert_eq!(fast.poll(), Poll::Pending);
+        for i in 1..4 {
+            assert_eq!(slow.poll(), Poll::Ready(Some(i)));
+        }
+        assert_eq!(fast.poll(), Poll::Ready(Some(4)));
+        assert_eq!(fast.poll(), Poll::Pending);
+        assert_eq!(slow.poll(), Poll::Ready(Some(4)));
+        assert_eq!(fast.poll(), Poll::Ready(Some(5)));
+    }
+
+    #[test]
+    fn test_capacity_wakes_only_for_slowest() {
+        let mut fast = Consumer::new(stream::iter(0..10).shared_with_capacity(2));
+        let mut middle = Consumer::new(fast.stream.clone());
+        let mut slow = Consumer::new(fast.stream.clone());
+        assert_eq!(fast.poll(), Poll::Ready(Some(0)));
+        assert_eq!(fast.poll(), Poll::Ready(Some(1)));
+        assert_eq!(fast.poll(), Poll::Pending);
+
+        assert_eq!(middle.poll(), Poll::Ready(Some(0)));
+        // `slow` is still at the first item, but was at the floor as well
+        assert!(fast.take_woken());
+        assert_eq!(fast.poll(), Poll::Pending);
+        // handles ahead of the slowest one advancing doesn't make room
+        assert_eq!(middle.poll(), Poll::Ready(Some(1)));
+        assert!(!fast.take_woken());
+        assert_eq!(slow.poll(), Poll::Ready(Some(0)));
+        assert!(fast.take_woken());
+        assert_eq!(fast.poll(), Poll::Ready(Some(2)));
+    }
+
+    #[test]
+    fn test_windows_with_capacity() {
+        let windows = stream::iter(1..=5).shared_with_capacity(3).windows(3);
+        assert_eq!(collect(windows), [[1, 2, 3], [2, 3, 4], [3, 4, 5]]);
+    }
+
+    #[test]
+    #[should_panic(expected = "window size must not exceed the capacity")]
+    fn test_windows_exceeding_capacity() {
+        let _ = stream::iter(1..=10).shared_with_capacity(2).windows(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "shared stream was created without a capacity")]
+    fn test_set_capacity_unbounded() {
+        stream::iter(0..3).shared().set_capacity(1);
+    }
+
+    #[test]
+    fn test_pending_consumer_is_woken_when_other_consumer_advances() {
+        let (mut sender, receiver) = channel(1);
+        let mut a = Consumer::new(receiver.shared());
+        let mut b = Consumer::new(a.stream.clone());
+
+        assert_eq!(a.poll(), Poll::Pending);
+        sender.try_send(1).unwrap();
+        assert!(a.take_woken());
+
+        // b drives the upstream and caches the item before a gets to poll again
+        assert_eq!(b.poll(), Poll::Ready(Some(1)));
+        assert_eq!(a.poll(), Poll::Ready(Some(1)));
+
+        assert_eq!(b.poll(), Poll::Pending);
+        assert_eq!(a.poll(), Poll::Pending);
+        drop(sender);
+        assert!(a.take_woken());
+        assert!(b.take_woken());
+        assert_eq!(a.poll(), Poll::Ready(None));
+        assert_eq!(b.poll(), Poll::Ready(None));
+    }
+
+    #[test]
+    fn test_every_pending_consumer_is_woken() {
+        let (mut sender, receiver) = channel(1);
+        let shared = receiver.ashared();
+        let mut consumers: Vec<_> = [shared.clone(), shared.clone(), shared]
+            .into_iter()
+            .map(Consumer::new)
+            .collect();
+
+        for consumer in &mut consumers {
+            assert_eq!(consumer.poll(), Poll::Pending);
+        }
+        sender.try_send(1).unwrap();
+        for consumer in &mut consumers {
+            assert!(consumer.take_woken());
+            assert_eq!(consumer.poll(), Poll::Ready(Some(1)));
+        }
+    }
+
+    #[test]
+    fn test_reset_all_wakes_pending_consumers() {
+        let (_sender, receiver) = channel::<i32>(1);
+        let mut a = Consumer::new(receiver.shared());
+        assert_eq!(a.poll(), Poll::Pending);
+
+        let (mut sender, receiver) = channel(1);
+        a.stream.replace_source(receiver);
+        assert!(a.take_woken());
+        assert_eq!(a.poll(), Poll::Pending);
+        a.stream.reset_all();
+        assert!(a.take_woken());
+        sender.try_send(1).unwrap();
+        assert_eq!(a.poll(), Poll::Ready(Some(1)));
+    }
+
+    /// A xorshift generator, so random operation sequences are reproducible from their seed.
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, n: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            usize::try_from(self.0 % n as u64).unwrap()
+        }
+    }
+
+    /// Applies random clone, poll, skip and drop operations to handles of a shared stream over
+    /// `0..len` and checks the bookkeeping invariants after every operation.
+    fn check_random_operations<S>(seed: u64, len: usize, make: impl FnOnce(usize) -> S)
+    where
+        S: Clone + Stream<Item = usize> + FusedStream + Unpin,
+    {
+        let mut rng = Rng(seed);
+        // every handle with the position it should be at
+        let mut handles = vec![(make(len), 0)];
+        let mut finished = false;
+        for _ in 0..64 {
+            let i = rng.below(handles.len());
+            match rng.below(4) {
+                0 => {
+                    let (handle, pos) = &handles[i];
+                    handles.push((handle.clone(), *pos));
+                }
+                1 => {
+                    let (handle, pos) = &mut handles[i];
+                    let expected = (*pos < len).then_some(*pos);
+                    assert_eq!(block_on(handle.next()), expected, "seed {seed}");
+                    if expected.is_some() {
+                        *pos += 1;
+                    } else {
+                        finished = true;
+                    }
+                }
+                2 => {
+                    let (handle, pos) = &mut handles[i];
+                    let n = rng.below(4);
+                    let skipped = collect(handle.take(n));
+                    assert_eq!(skipped, (*pos..len.min(*pos + n)).collect::<Vec<_>>());
+                    *pos += skipped.len();
+                    // falling short means the end of the stream was polled
+                    finished |= skipped.len() < n;
+                }
+                _ if handles.len() > 1 => drop(handles.swap_remove(i)),
+                _ => {}
+            }
+            for (handle, pos) in &handles {
+                let remaining = len - pos;
+                assert_eq!(
+                    handle.size_hint(),
+                    (remaining, Some(remaining)),
+                    "seed {seed}"
+                );
+                assert_eq!(
+                    handle.is_terminated(),
+                    finished && remaining == 0,
+                    "seed {seed}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_random_operations() {
+        for seed in 1..=200 {
+            let len = usize::try_from(seed % 12).unwrap();
+            check_random_operations(seed, len, |len| stream::iter(0..len).shared());
+            check_random_operations(seed, len, |len| stream::iter(0..len).ashared());
+        }
+    }
+
+    #[test]
+    fn test_poll_until_finished() {
+        let (mut sender, receiver) = channel(4);
+        let shared = receiver.ashared();
+        let flag = Arc::new(FlagWaker(AtomicBool::new(false)));
+        let waker = waker(Arc::clone(&flag));
+        let mut cx = Context::from_waker(&waker);
+
+        assert_eq!(shared.poll_until_finished(&mut cx), Poll::Pending);
+        sender.try_send(1).unwrap();
+        sender.try_send(2).unwrap();
+        assert!(flag.0.swap(false, Ordering::SeqCst));
+        assert_eq!(shared.poll_until_finished(&mut cx), Poll::Pending);
+        assert_eq!(shared.inner.read().values, [1, 2]);
+        assert!(!shared.is_terminated());
+
+        drop(sender);
+        assert!(flag.0.swap(false, Ordering::SeqCst));
+        assert_eq!(shared.poll_until_finished(&mut cx), Poll::Ready(()));
+        assert!(shared.inner.read().stream.is_none());
+        assert_eq!(shared.size_hint(), (2, Some(2)));
+        assert_eq!(collect(shared.clone()), [1, 2]);
+        assert_eq!(shared.poll_until_finished(&mut cx), Poll::Ready(()));
+
+        let shared = stream::iter(1..=3).shared();
+        assert_eq!(shared.poll_until_finished(&mut cx), Poll::Ready(()));
+        assert_eq!(collect(shared), [1, 2, 3]);
+    }
 }
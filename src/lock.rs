@@ -0,0 +1,241 @@
+//! Lock primitives backing [`Ashared`](crate::Ashared) and the shared waker list.
+//!
+//! The locks of `std::sync` are used if the `std` feature is enabled. Otherwise, the `spin`
+//! feature provides a busy-waiting lock that works without `std`. Enabling both keeps the locks
+//! of `std::sync`, so that the features are additive.
+//!
+//! Poisoning is ignored: the shared state is never left inconsistent by a panic, since items are
+//! only pushed after the underlying stream has returned them. A panicking underlying stream is
+//! simply polled again by the next handle.
+
+// `pub(crate)` is required by the `unreachable_pub` lint
+#![allow(clippy::redundant_pub_crate)]
+
+// without either feature, the spin locks keep the `compile_error!` the only error
+#[cfg(not(feature = "std"))]
+pub(crate) use self::spin::{Mutex, RwLock};
+#[cfg(feature = "std")]
+pub(crate) use self::std_sync::{Mutex, RwLock};
+
+#[cfg(feature = "std")]
+mod std_sync {
+    use core::fmt;
+    use std::sync::{self, PoisonError, TryLockError};
+
+    pub(crate) type ReadGuard<'a, T> = sync::RwLockReadGuard<'a, T>;
+    pub(crate) type WriteGuard<'a, T> = sync::RwLockWriteGuard<'a, T>;
+    pub(crate) type MutexGuard<'a, T> = sync::MutexGuard<'a, T>;
+
+    /// Reader-writer lock storing the state of [`Ashared`](crate::Ashared).
+    // `pub` since it is part of `Storage::Ptr`, but unreachable from outside the crate
+    pub struct RwLock<T>(sync::RwLock<T>);
+
+    impl<T> RwLock<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self(sync::RwLock::new(value))
+        }
+
+        pub(crate) fn read(&self) -> ReadGuard<'_, T> {
+            self.0.read().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub(crate) fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+            match self.0.try_read() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            }
+        }
+
+        pub(crate) fn write(&self) -> WriteGuard<'_, T> {
+            self.0.write().unwrap_or_else(PoisonError::into_inner)
+        }
+
+        pub(crate) fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+            match self.0.try_write() {
+                Ok(guard) => Some(guard),
+                Err(TryLockError::Poisoned(err)) => Some(err.into_inner()),
+                Err(TryLockError::WouldBlock) => None,
+            }
+        }
+
+        pub(crate) fn into_inner(self) -> T {
+            self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+
+    pub(crate) struct Mutex<T>(sync::Mutex<T>);
+
+    impl<T> Mutex<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self(sync::Mutex::new(value))
+        }
+
+        pub(crate) fn lock(&self) -> MutexGuard<'_, T> {
+            self.0.lock().unwrap_or_else(PoisonError::into_inner)
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for Mutex<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[cfg(not(feature = "std"))]
+mod spin {
+    use core::cell::UnsafeCell;
+    use core::fmt;
+    use core::hint;
+    use core::ops::{Deref, DerefMut};
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Lock state signalling that a writer holds the lock. Any other value is the reader count.
+    const WRITER: usize = usize::MAX;
+
+    /// Reader-writer lock storing the state of [`Ashared`](crate::Ashared).
+    // `pub` since it is part of `Storage::Ptr`, but unreachable from outside the crate
+    pub struct RwLock<T> {
+        state: AtomicUsize,
+        value: UnsafeCell<T>,
+    }
+
+    // Same bounds as `std::sync::RwLock`: the lock hands out `&T` to several threads at once.
+    unsafe impl<T: Send> Send for RwLock<T> {}
+    unsafe impl<T: Send + Sync> Sync for RwLock<T> {}
+
+    impl<T> RwLock<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self {
+                state: AtomicUsize::new(0),
+                value: UnsafeCell::new(value),
+            }
+        }
+
+        pub(crate) fn try_read(&self) -> Option<ReadGuard<'_, T>> {
+            let mut state = self.state.load(Ordering::Relaxed);
+            // `WRITER - 1` readers would make the next reader look like a writer. A failed
+            // exchange, whether spurious or because another reader came or went, is retried, so
+            // that only a writer makes this fail.
+            while state < WRITER - 1 {
+                match self.state.compare_exchange_weak(
+                    state,
+                    state + 1,
+                    Ordering::Acquire,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return Some(ReadGuard { lock: self }),
+                    Err(current) => state = current,
+                }
+            }
+            None
+        }
+
+        pub(crate) fn read(&self) -> ReadGuard<'_, T> {
+            loop {
+                if let Some(guard) = self.try_read() {
+                    return guard;
+                }
+                hint::spin_loop();
+            }
+        }
+
+        pub(crate) fn write(&self) -> WriteGuard<'_, T> {
+            while self
+                .state
+                .compare_exchange_weak(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                hint::spin_loop();
+            }
+            WriteGuard { lock: self }
+        }
+
+        pub(crate) fn try_write(&self) -> Option<WriteGuard<'_, T>> {
+            self.state
+                .compare_exchange(0, WRITER, Ordering::Acquire, Ordering::Relaxed)
+                .ok()
+                .map(|_| WriteGuard { lock: self })
+        }
+
+        pub(crate) fn into_inner(self) -> T {
+            self.value.into_inner()
+        }
+    }
+
+    impl<T: fmt::Debug> fmt::Debug for RwLock<T> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            let mut d = f.debug_struct("RwLock");
+            match self.try_read() {
+                Some(guard) => d.field("data", &&*guard).finish_non_exhaustive(),
+                None => d
+                    .field("data", &format_args!("<locked>"))
+                    .finish_non_exhaustive(),
+            }
+        }
+    }
+
+    pub(crate) struct ReadGuard<'a, T> {
+        lock: &'a RwLock<T>,
+    }
+
+    impl<T> Deref for ReadGuard<'_, T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            // readers only ever get shared access while the reader count is non-zero
+            unsafe { &*self.lock.value.get() }
+        }
+    }
+
+    impl<T> Drop for ReadGuard<'_, T> {
+        fn drop(&mut self) {
+            let _ = self.lock.state.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    pub(crate) struct WriteGuard<'a, T> {
+        lock: &'a RwLock<T>,
+    }
+
+    impl<T> Deref for WriteGuard<'_, T> {
+        type Target = T;
+        fn deref(&self) -> &T {
+            // the writer has exclusive access until the guard is dropped
+            unsafe { &*self.lock.value.get() }
+        }
+    }
+
+    impl<T> DerefMut for WriteGuard<'_, T> {
+        fn deref_mut(&mut self) -> &mut T {
+            // the writer has exclusive access until the guard is dropped
+            unsafe { &mut *self.lock.value.get() }
+        }
+    }
+
+    impl<T> Drop for WriteGuard<'_, T> {
+        fn drop(&mut self) {
+            self.lock.state.store(0, Ordering::Release);
+        }
+    }
+
+    /// A spin mutex, which is a [`RwLock`] that is only ever locked for writing.
+    #[derive(Debug)]
+    pub(crate) struct Mutex<T>(RwLock<T>);
+
+    impl<T> Mutex<T> {
+        pub(crate) const fn new(value: T) -> Self {
+            Self(RwLock::new(value))
+        }
+
+        pub(crate) fn lock(&self) -> WriteGuard<'_, T> {
+            self.0.write()
+        }
+    }
+}
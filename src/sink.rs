@@ -0,0 +1,112 @@
+//! Forwarding of [`Sink`] to the underlying stream.
+
+use crate::state::Position;
+use crate::storage::Storage;
+use crate::SharedStream;
+use alloc::sync::Arc;
+use core::error::Error;
+use core::fmt;
+use core::pin::Pin;
+use core::ptr;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+use futures_sink::Sink;
+
+/// Error returned by the [`Sink`] implementation of [`SharedStream`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SinkError<E> {
+    /// Another handle of the shared stream already uses the underlying stream as a sink.
+    Busy,
+    /// The underlying stream has finished, so it has been dropped together with its sink.
+    Finished,
+    /// The underlying sink returned an error.
+    Sink(E),
+}
+
+impl<E> fmt::Display for SinkError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Busy => "another handle already uses the underlying sink",
+            Self::Finished => "the underlying stream has finished",
+            Self::Sink(_) => "the underlying sink returned an error",
+        })
+    }
+}
+
+impl<E: Error + 'static> Error for SinkError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Sink(e) => Some(e),
+            Self::Busy | Self::Finished => None,
+        }
+    }
+}
+
+impl<S: Stream, M: Storage<S>> SharedStream<S, M> {
+    /// Calls `f` with the underlying stream, which this handle claims as its sink unless another
+    /// handle already has.
+    fn with_sink<T, E>(&mut self, f: impl FnOnce(Pin<&mut S>) -> T) -> Result<T, SinkError<E>> {
+        // the owner is told apart by its position, which handles of shared streams that don't
+        // track positions only get once they claim the sink
+        let position = self
+            .position
+            .get_or_insert_with(|| Arc::new(Position::new(self.idx)));
+        let mut inner = M::write(&self.inner);
+        let owner = &mut inner.sink_owner;
+        if owner.strong_count() == 0 {
+            *owner = Arc::downgrade(position);
+        } else if !ptr::eq(owner.as_ptr(), Arc::as_ptr(position)) {
+            return Err(SinkError::Busy);
+        }
+        // the state is pinned behind the pointer, see `poll_item`
+        let inner = unsafe { Pin::new_unchecked(&mut *inner) };
+        inner
+            .project()
+            .stream
+            .as_pin_mut()
+            .map(f)
+            .ok_or(SinkError::Finished)
+    }
+
+    fn poll_sink<E>(
+        &mut self,
+        f: impl FnOnce(Pin<&mut S>) -> Poll<Result<(), E>>,
+    ) -> Poll<Result<(), SinkError<E>>> {
+        match self.with_sink(f) {
+            Ok(poll) => poll.map_err(SinkError::Sink),
+            Err(e) => Poll::Ready(Err(e)),
+        }
+    }
+}
+
+/// Forwards to the underlying stream, if it is a [`Sink`] as well.
+///
+/// The first handle that is used as a sink claims the underlying sink until it is dropped.
+/// Meanwhile, the other handles fail with [`SinkError::Busy`]. Once the underlying stream has
+/// finished, it is dropped along with its sink, so all handles fail with
+/// [`SinkError::Finished`].
+impl<S, M, Item> Sink<Item> for SharedStream<S, M>
+where
+    S: Stream + Sink<Item>,
+    M: Storage<S>,
+{
+    type Error = SinkError<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink(|sink| sink.poll_ready(cx))
+    }
+
+    fn start_send(self: Pin<&mut Self>, item: Item) -> Result<(), Self::Error> {
+        self.get_mut()
+            .with_sink(|sink| sink.start_send(item))?
+            .map_err(SinkError::Sink)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink(|sink| sink.poll_flush(cx))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_sink(|sink| sink.poll_close(cx))
+    }
+}
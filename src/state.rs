@@ -0,0 +1,613 @@
+//! State shared by all handles of a shared stream.
+
+// `pub(crate)` is required by the `unreachable_pub` lint
+#![allow(clippy::redundant_pub_crate)]
+
+use crate::growth::Growth;
+use crate::lock::Mutex;
+use crate::storage::Storage;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use core::task::{Context, Poll, Waker};
+use futures_core::Stream;
+use futures_util::task::{waker_ref, ArcWake};
+use pin_project_lite::pin_project;
+
+#[derive(Debug)]
+pub(crate) struct SharedWaker(Mutex<Vec<Waker>>);
+
+impl SharedWaker {
+    pub(crate) fn add_waker(&self, cx: &Context<'_>) {
+        self.0.lock().push(cx.waker().clone());
+    }
+}
+
+impl ArcWake for SharedWaker {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        let wakers = mem::take(&mut *arc_self.0.lock());
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+/// Position of a handle, which is the index of the next item it yields.
+#[derive(Debug)]
+pub(crate) struct Position {
+    idx: AtomicUsize,
+    // set if the buffer is bounded
+    floor: Option<Arc<Floor>>,
+    fairness: Option<Arc<Fairness>>,
+}
+
+impl Position {
+    /// Creates a position that isn't registered with the state, which only identifies a handle.
+    #[cfg(feature = "sink")]
+    pub(crate) const fn new(idx: usize) -> Self {
+        Self {
+            idx: AtomicUsize::new(idx),
+            floor: None,
+            fairness: None,
+        }
+    }
+
+    pub(crate) fn load(&self) -> usize {
+        self.idx.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn store(&self, idx: usize) {
+        let old = self.idx.swap(idx, Ordering::SeqCst);
+        if let Some(floor) = &self.floor {
+            // only the slowest handle advancing makes room in the buffer
+            if idx > old && old <= floor.idx.load(Ordering::SeqCst) {
+                ArcWake::wake_by_ref(&floor.waker);
+            }
+        }
+    }
+
+    /// Marks the handle as being polled until the returned guard is dropped, if handles take
+    /// turns polling the underlying stream.
+    pub(crate) fn enter(&self) -> Option<Polling> {
+        let fairness = Arc::clone(self.fairness.as_ref()?);
+        let _ = fairness.polling.fetch_add(1, Ordering::SeqCst);
+        Some(Polling {
+            fairness,
+            yielded: false,
+        })
+    }
+}
+
+impl Drop for Position {
+    fn drop(&mut self) {
+        // the handle might have been the one holding back the others
+        if let Some(floor) = &self.floor {
+            ArcWake::wake_by_ref(&floor.waker);
+        }
+    }
+}
+
+/// Position of the slowest handle of a bounded buffer, as last seen by a handle that found the
+/// buffer full.
+#[derive(Debug)]
+pub(crate) struct Floor {
+    idx: AtomicUsize,
+    // woken when the slowest handle advances or a handle is dropped
+    waker: Arc<SharedWaker>,
+}
+
+/// Turns of the handles polling the underlying stream, see
+/// [`ashared_fair`](crate::Share::ashared_fair).
+#[derive(Debug)]
+pub(crate) struct Fairness {
+    // number of handles that are being polled
+    polling: AtomicUsize,
+    // handles that have given up their turn
+    waiting: Arc<SharedWaker>,
+}
+
+impl Fairness {
+    pub(crate) fn new() -> Self {
+        Self {
+            polling: AtomicUsize::new(0),
+            waiting: Arc::new(SharedWaker(Mutex::new(vec![]))),
+        }
+    }
+
+    /// Number of handles that are being polled.
+    #[cfg(test)]
+    pub(crate) fn polling(&self) -> usize {
+        self.polling.load(Ordering::SeqCst)
+    }
+}
+
+/// Guard of a handle that is being polled, see [`Position::enter`].
+pub(crate) struct Polling {
+    fairness: Arc<Fairness>,
+    // whether the handle has given up its turn, in which case it must not be woken right away
+    pub(crate) yielded: bool,
+}
+
+impl Drop for Polling {
+    fn drop(&mut self) {
+        let _ = self.fairness.polling.fetch_sub(1, Ordering::SeqCst);
+        if !self.yielded {
+            ArcWake::wake_by_ref(&self.fairness.waiting);
+        }
+    }
+}
+
+#[cfg(feature = "sink")]
+type SinkOwner = Weak<Position>;
+#[cfg(not(feature = "sink"))]
+type SinkOwner = ();
+
+/// Duplicates buffered items for the handles yielding them.
+pub(crate) enum Cloner<S: Stream, M: Storage<S>> {
+    /// [`Clone::clone`], which isn't boxed so that items don't have to be `'static`.
+    Clone(fn(&S::Item) -> S::Item),
+    /// The function passed to [`shared_with`](crate::Share::shared_with).
+    With(M::CloneFn),
+}
+
+impl<S: Stream, M: Storage<S>> Cloner<S, M> {
+    fn clone_item(&self, item: &S::Item) -> S::Item {
+        match self {
+            Self::Clone(f) => f(item),
+            Self::With(f) => f(item),
+        }
+    }
+}
+
+impl<S: Stream, M: Storage<S>> fmt::Debug for Cloner<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Clone(_) => "Clone",
+            Self::With(_) => "With",
+        })
+    }
+}
+
+/// Callbacks that are invoked once for every item produced by the underlying stream.
+pub(crate) struct Observers<S: Stream, M: Storage<S>>(pub(crate) Vec<M::Observer>);
+
+impl<S: Stream, M: Storage<S>> Observers<S, M> {
+    fn notify(&mut self, item: &S::Item) {
+        for observer in &mut self.0 {
+            observer(item);
+        }
+    }
+}
+
+impl<S: Stream, M: Storage<S>> fmt::Debug for Observers<S, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Observers")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+pin_project! {
+    /// State shared by all handles of a shared stream.
+    // `pub` since it is part of `Storage`, but unreachable from outside the crate
+    #[project = InnerStateProj]
+    #[derive(Debug)]
+    pub struct InnerState<S: Stream, M: Storage<S>> {
+        pub(crate) values: Vec<S::Item>,
+        // number of items dropped from the front of `values`, so the first buffered item is the
+        // item at this position
+        pub(crate) reclaimed: usize,
+        // whether items every handle has yielded are dropped
+        pub(crate) reclaim: bool,
+        // whether handles register their positions even if nothing else needs them
+        pub(crate) track_positions: bool,
+        // number of items the underlying stream has produced, including dropped ones
+        pub(crate) produced: usize,
+        #[pin]
+        pub(crate) stream: Option<S>,
+        pub(crate) waker: Arc<SharedWaker>,
+        pub(crate) observers: Observers<S, M>,
+        pub(crate) cloner: Cloner<S, M>,
+        // incremented whenever the buffer is reset, which moves every handle back to the start
+        pub(crate) generation: usize,
+        // positions of all handles
+        pub(crate) cursors: Vec<Weak<Position>>,
+        // maximum number of items the slowest handle may lag behind
+        pub(crate) max_buffered: Option<usize>,
+        // set together with `max_buffered`
+        pub(crate) floor: Option<Arc<Floor>>,
+        // number of most recent items to keep, regardless of the positions of the handles
+        pub(crate) replay_last: Option<usize>,
+        // maximum number of times a handle polls the underlying stream before yielding
+        pub(crate) budget: Option<usize>,
+        // maximum number of handles `try_clone` allows
+        pub(crate) max_handles: usize,
+        pub(crate) growth: Option<Growth>,
+        // handle that uses the underlying stream as a sink, see `sink.rs`
+        pub(crate) sink_owner: SinkOwner,
+        // set if handles take turns polling the underlying stream
+        pub(crate) fairness: Option<Arc<Fairness>>,
+        // handle that polled the underlying stream last, if handles take turns
+        pub(crate) driver: Weak<Position>,
+    }
+}
+
+impl<S: Stream, M: Storage<S>> InnerState<S, M> {
+    pub(crate) fn new(stream: S) -> Self
+    where
+        S::Item: Clone,
+    {
+        Self::with_cloner(stream, Cloner::Clone(S::Item::clone))
+    }
+
+    pub(crate) fn with_cloner(stream: S, cloner: Cloner<S, M>) -> Self {
+        Self {
+            stream: Some(stream),
+            values: vec![],
+            reclaimed: 0,
+            reclaim: false,
+            track_positions: false,
+            produced: 0,
+            waker: Arc::new(SharedWaker(Mutex::new(vec![]))),
+            observers: Observers(vec![]),
+            cloner,
+            generation: 0,
+            cursors: vec![],
+            max_buffered: None,
+            floor: None,
+            replay_last: None,
+            budget: None,
+            max_handles: usize::MAX,
+            growth: None,
+            sink_owner: SinkOwner::default(),
+            fairness: None,
+            driver: Weak::new(),
+        }
+    }
+
+    pub(crate) fn bounded(stream: S, cap: usize) -> Self
+    where
+        S::Item: Clone,
+    {
+        assert!(cap > 0, "capacity must be non-zero");
+        let state = Self::new(stream);
+        Self {
+            reclaim: true,
+            max_buffered: Some(cap),
+            floor: Some(Arc::new(Floor {
+                idx: AtomicUsize::new(0),
+                waker: Arc::clone(&state.waker),
+            })),
+            ..state
+        }
+    }
+
+    /// Changes the capacity of a bounded buffer, see [`SharedStream::set_capacity`].
+    ///
+    /// [`SharedStream::set_capacity`]: crate::SharedStream::set_capacity
+    pub(crate) fn set_capacity(&mut self, cap: usize) {
+        assert!(cap > 0, "capacity must be non-zero");
+        let max = self
+            .max_buffered
+            .as_mut()
+            .expect("shared stream was created without a capacity");
+        let lowered = cap < *max;
+        *max = cap;
+        if lowered {
+            // drop the items every handle has yielded right away, instead of once enough of them
+            // have piled up
+            let consumed = min_position(&self.cursors).map_or(0, |min| {
+                min.saturating_sub(self.reclaimed).min(self.values.len())
+            });
+            drop(self.values.drain(..consumed));
+            self.reclaimed += consumed;
+        } else {
+            // handles waiting for the slowest one may be able to continue
+            ArcWake::wake_by_ref(&self.waker);
+        }
+    }
+
+    /// Returns whether handles register their positions, which is only needed to drop items
+    /// every handle has yielded, to bound the buffer, to take turns, to limit the number of
+    /// handles, or if asked for.
+    pub(crate) const fn tracks_positions(&self) -> bool {
+        self.track_positions
+            || self.reclaim
+            || self.max_buffered.is_some()
+            || self.fairness.is_some()
+            || self.max_handles != usize::MAX
+    }
+
+    /// Registers the position of a new handle, unless positions aren't tracked.
+    pub(crate) fn register(&mut self, idx: usize) -> Option<Arc<Position>> {
+        if !self.tracks_positions() {
+            return None;
+        }
+        self.cursors.retain(|cursor| cursor.strong_count() > 0);
+        let position = Arc::new(Position {
+            idx: AtomicUsize::new(idx),
+            floor: self.floor.clone(),
+            fairness: self.fairness.clone(),
+        });
+        self.cursors.push(Arc::downgrade(&position));
+        Some(position)
+    }
+
+    /// Returns whether the handle at `position` may poll the underlying stream, which it may not
+    /// if it polled it last while other handles are being polled.
+    pub(crate) fn take_turn(&mut self, position: Option<&Arc<Position>>, cx: &Context<'_>) -> bool {
+        let (Some(fairness), Some(position)) = (&self.fairness, position) else {
+            return true;
+        };
+        if ptr::eq(self.driver.as_ptr(), Arc::as_ptr(position)) {
+            // the waker has to be added before checking, or the other handles being done in
+            // between would be missed
+            fairness.waiting.add_waker(cx);
+            if fairness.polling.load(Ordering::SeqCst) > 1 {
+                return false;
+            }
+        }
+        self.driver = Arc::downgrade(position);
+        true
+    }
+
+    /// Like [`register`](Self::register), but fails if there are already `max_handles` handles.
+    #[allow(clippy::option_option)]
+    pub(crate) fn try_register(&mut self, idx: usize) -> Option<Option<Arc<Position>>> {
+        self.cursors.retain(|cursor| cursor.strong_count() > 0);
+        (self.cursors.len() < self.max_handles).then(|| self.register(idx))
+    }
+
+    pub(crate) fn positions(&self) -> Vec<usize> {
+        self.cursors
+            .iter()
+            .filter_map(Weak::upgrade)
+            .map(|position| position.load())
+            .collect()
+    }
+
+    /// Returns whether the slowest handle lags `max_buffered` items behind, so no more items may
+    /// be buffered until it advances.
+    fn is_full(&self) -> bool {
+        self.max_buffered.is_some_and(|max| {
+            min_position(&self.cursors)
+                .is_some_and(|min| (self.reclaimed + self.values.len()).saturating_sub(min) >= max)
+        })
+    }
+
+    /// Returns the position of a handle, which is back at the start if the buffer has been reset
+    /// since the handle was last polled, and at the first buffered item if the items it would
+    /// yield next have been dropped.
+    pub(crate) const fn cursor(&self, generation: usize, idx: usize) -> usize {
+        if generation != self.generation {
+            0
+        } else if idx < self.reclaimed {
+            self.reclaimed
+        } else {
+            idx
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        self.values.clear();
+        self.reclaimed = 0;
+        self.generation = self.generation.wrapping_add(1);
+        for position in self.cursors.iter().filter_map(Weak::upgrade) {
+            position.store(0);
+        }
+        ArcWake::wake_by_ref(&self.waker);
+    }
+
+    pub(crate) fn replace_source(self: Pin<&mut Self>, stream: S) {
+        let mut this = self.project();
+        this.stream.set(Some(stream));
+        // handles waiting for the old stream have to poll the new one
+        ArcWake::wake_by_ref(this.waker);
+    }
+
+    pub(crate) fn close_source(mut self: Pin<&mut Self>) {
+        self.as_mut().finish();
+        // handles waiting for the next item have to notice the end
+        ArcWake::wake_by_ref(&self.waker);
+    }
+
+    pub(crate) const fn estimated_memory(&self) -> usize {
+        size_of::<Self>() + self.values.capacity() * size_of::<S::Item>()
+    }
+
+    pub(crate) fn estimated_memory_with(&self, f: impl Fn(&S::Item) -> usize) -> usize {
+        self.estimated_memory() + self.values.iter().map(f).sum::<usize>()
+    }
+
+    pub(crate) fn stream_is_pending(mut self: Pin<&mut Self>, cx: &Context<'_>) -> bool {
+        if self.is_full() {
+            // the waker has to be added and the position of the slowest handle published before
+            // checking again, or the slowest handle advancing in between would be missed
+            self.waker.add_waker(cx);
+            if let (Some(floor), Some(min)) = (&self.floor, min_position(&self.cursors)) {
+                floor.idx.store(min, Ordering::SeqCst);
+            }
+            if self.is_full() {
+                return true;
+            }
+        }
+        let polled = {
+            let this = self.as_mut().project();
+            // the stream is dropped once it has finished, so sources that aren't fused are never
+            // polled again after returning `None`
+            let stream = this
+                .stream
+                .as_pin_mut()
+                .expect("underlying stream polled after it finished");
+            let waker = waker_ref(this.waker);
+            let mut up_cx = Context::from_waker(&waker);
+            // nothing is mutated before the stream returns, so if it panics, the state is left as
+            // it was and the next handle simply polls it again
+            stream.poll_next(&mut up_cx)
+        };
+        match polled {
+            Poll::Ready(Some(v)) => self.push(v),
+            Poll::Ready(None) => self.finish(),
+            Poll::Pending => {
+                self.waker.add_waker(cx);
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Buffers an item produced by the underlying stream.
+    pub(crate) fn push(self: Pin<&mut Self>, v: S::Item) {
+        let this = self.project();
+        // the handle yielding this item moves on to the next index, which has to fit as well
+        assert!(
+            this.reclaimed.checked_add(this.values.len() + 1).is_some(),
+            "shared stream has more items than positions fit in a usize"
+        );
+        this.observers.notify(&v);
+        if *this.reclaim {
+            *this.reclaimed += reclaim(this.values, *this.reclaimed, this.cursors);
+        }
+        this.values.push(v);
+        *this.produced = this.produced.saturating_add(1);
+        if let Some(n) = *this.replay_last {
+            let excess = this.values.len().saturating_sub(n);
+            drop(this.values.drain(..excess));
+            *this.reclaimed += excess;
+        }
+        if let Some(growth) = this.growth {
+            growth.grow(this.values);
+        }
+    }
+
+    /// Drops the underlying stream once it has finished.
+    pub(crate) fn finish(self: Pin<&mut Self>) {
+        let mut this = self.project();
+        this.stream.set(None);
+        // the buffer never grows again, so its spare capacity would be wasted
+        this.values.shrink_to_fit();
+    }
+
+    pub(crate) fn poll_until_finished(self: Pin<&mut Self>, cx: &Context<'_>) -> Poll<()> {
+        self.poll_until(cx, usize::MAX)
+    }
+
+    /// Polls the underlying stream until it has produced the items before `end` or finished.
+    pub(crate) fn poll_until(mut self: Pin<&mut Self>, cx: &Context<'_>, end: usize) -> Poll<()> {
+        let mut polled = 0;
+        while self.stream.is_some() && self.reclaimed + self.values.len() < end {
+            if self.budget_exhausted(&mut polled, cx) {
+                return Poll::Pending;
+            }
+            if self.as_mut().stream_is_pending(cx) {
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Counts another poll of the underlying stream in `polled`. Once the budget is exhausted,
+    /// the task of `cx` is woken right away, so the caller can yield to the executor.
+    pub(crate) fn budget_exhausted(&self, polled: &mut usize, cx: &Context<'_>) -> bool {
+        if self.budget == Some(*polled) {
+            cx.waker().wake_by_ref();
+            return true;
+        }
+        *polled += 1;
+        false
+    }
+
+    pub(crate) fn compact_finished(&mut self) {
+        if self.stream.is_none() {
+            self.values.shrink_to_fit();
+        }
+    }
+
+    #[allow(clippy::option_option)]
+    pub(crate) fn known_value(&self, idx: usize) -> Option<Option<S::Item>> {
+        assert!(idx >= self.reclaimed, "item has already been reclaimed");
+        let value = self
+            .values
+            .get(idx - self.reclaimed)
+            .map(|v| self.cloner.clone_item(v));
+        if value.is_some() || self.stream.is_none() {
+            return Some(value);
+        }
+        None
+    }
+
+    /// Clones at most `max` buffered items from `offset` on, without polling the underlying
+    /// stream.
+    pub(crate) fn buffered_items(&self, offset: usize, max: usize) -> Vec<S::Item> {
+        let start = offset.saturating_sub(self.reclaimed).min(self.values.len());
+        self.values[start..]
+            .iter()
+            .take(max)
+            .map(|v| self.cloner.clone_item(v))
+            .collect()
+    }
+
+    pub(crate) fn size_hint(&self, offset: usize) -> (usize, Option<usize>) {
+        let end = self.reclaimed + self.values.len();
+        let upstream_cached = end.saturating_sub(offset);
+        // items a handle has seeked past without them being produced yet
+        let skipped = offset.saturating_sub(end);
+        self.stream
+            .as_ref()
+            .map_or((upstream_cached, Some(upstream_cached)), |stream| {
+                let upstream = stream.size_hint();
+                (
+                    upstream
+                        .0
+                        .saturating_sub(skipped)
+                        .saturating_add(upstream_cached),
+                    upstream
+                        .1
+                        .and_then(|v| v.saturating_sub(skipped).checked_add(upstream_cached)),
+                )
+            })
+    }
+
+    pub(crate) const fn is_terminated(&self, offset: usize) -> bool {
+        self.stream.is_none() && self.reclaimed + self.values.len() <= offset
+    }
+
+    /// Clones the items from `offset` on, if the underlying stream has finished.
+    pub(crate) fn finished_items(&self, offset: usize) -> Option<Vec<S::Item>> {
+        let start = offset.saturating_sub(self.reclaimed).min(self.values.len());
+        self.stream.is_none().then(|| {
+            self.values[start..]
+                .iter()
+                .map(|v| self.cloner.clone_item(v))
+                .collect()
+        })
+    }
+}
+
+fn min_position(cursors: &[Weak<Position>]) -> Option<usize> {
+    cursors
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|position| position.load())
+        .min()
+}
+
+/// Drops the items of `values` every handle has yielded, returning how many were dropped.
+///
+/// To keep this cheap, items are only dropped once they make up at least half of the buffer.
+fn reclaim<T>(values: &mut Vec<T>, reclaimed: usize, cursors: &[Weak<Position>]) -> usize {
+    // handles that have fallen behind the first buffered item haven't been polled since, and
+    // handles can seek past the last buffered item
+    let consumed =
+        min_position(cursors).map_or(0, |min| min.saturating_sub(reclaimed).min(values.len()));
+    if consumed == 0 || consumed * 2 < values.len() {
+        return 0;
+    }
+    drop(values.drain(..consumed));
+    consumed
+}
@@ -0,0 +1,156 @@
+//! Storage of the state shared by all handles of a shared stream.
+
+use crate::handle::{Local, ThreadSafe};
+use crate::lock::RwLock;
+use crate::state::InnerState;
+use alloc::boxed::Box;
+use alloc::rc::{self, Rc};
+use alloc::sync::{self, Arc};
+use core::cell::RefCell;
+use core::ops::{Deref, DerefMut};
+use futures_core::Stream;
+
+/// How the state of a shared stream is stored, which is what sets
+/// [`Shared`](crate::Shared) and [`Ashared`](crate::Ashared) apart.
+///
+/// The trait is public so it can bound [`SharedStream`](crate::SharedStream), but it isn't
+/// exported, so it can't be implemented outside of this crate.
+pub trait Storage<S: Stream>: Sized {
+    /// Pointer to the state, of which every handle holds a clone.
+    type Ptr: Clone + Unpin;
+
+    /// Pointer to the state that doesn't keep it alive.
+    type Weak: Clone;
+
+    /// Callback passed to [`add_item_observer`](crate::SharedStream::add_item_observer), which
+    /// only has to be `Send` and `Sync` if the state is shared across threads.
+    type Observer: FnMut(&S::Item);
+
+    /// Function passed to [`shared_with`](crate::Share::shared_with), which only has to be
+    /// `Send` and `Sync` if the state is shared across threads.
+    type CloneFn: Fn(&S::Item) -> S::Item;
+
+    /// Name of the handle type, used by its `Debug` implementation.
+    const NAME: &'static str;
+
+    /// Moves `state` behind a new pointer.
+    fn new(state: InnerState<S, Self>) -> Self::Ptr;
+
+    /// Borrows the state for reading.
+    fn read(ptr: &Self::Ptr) -> impl Deref<Target = InnerState<S, Self>> + '_;
+
+    /// Borrows the state for reading, unless it is currently borrowed for writing.
+    fn try_read(ptr: &Self::Ptr) -> Option<impl Deref<Target = InnerState<S, Self>> + '_>;
+
+    /// Borrows the state for writing.
+    fn write(ptr: &Self::Ptr) -> impl DerefMut<Target = InnerState<S, Self>> + '_;
+
+    /// Returns the number of pointers to the state, i.e. the number of handles.
+    fn strong_count(ptr: &Self::Ptr) -> usize;
+
+    /// Returns whether `a` and `b` point to the same state.
+    fn ptr_eq(a: &Self::Ptr, b: &Self::Ptr) -> bool;
+
+    /// Moves the state out of `ptr` if it is the only pointer to it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ptr` if there are other pointers to the state.
+    fn try_unwrap(ptr: Self::Ptr) -> Result<InnerState<S, Self>, Self::Ptr>;
+
+    /// Creates a weak pointer to the state of `ptr`.
+    fn downgrade(ptr: &Self::Ptr) -> Self::Weak;
+
+    /// Turns `weak` back into a pointer, unless the state has been dropped.
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Ptr>;
+}
+
+impl<S: Stream> Storage<S> for Local {
+    type Ptr = Rc<RefCell<InnerState<S, Self>>>;
+    type Weak = rc::Weak<RefCell<InnerState<S, Self>>>;
+    type Observer = Box<dyn FnMut(&S::Item)>;
+    type CloneFn = Box<dyn Fn(&S::Item) -> S::Item>;
+
+    const NAME: &'static str = "Shared";
+
+    fn new(state: InnerState<S, Self>) -> Self::Ptr {
+        Rc::new(RefCell::new(state))
+    }
+
+    fn read(ptr: &Self::Ptr) -> impl Deref<Target = InnerState<S, Self>> + '_ {
+        ptr.borrow()
+    }
+
+    fn try_read(ptr: &Self::Ptr) -> Option<impl Deref<Target = InnerState<S, Self>> + '_> {
+        ptr.try_borrow().ok()
+    }
+
+    fn write(ptr: &Self::Ptr) -> impl DerefMut<Target = InnerState<S, Self>> + '_ {
+        ptr.borrow_mut()
+    }
+
+    fn strong_count(ptr: &Self::Ptr) -> usize {
+        Rc::strong_count(ptr)
+    }
+
+    fn ptr_eq(a: &Self::Ptr, b: &Self::Ptr) -> bool {
+        Rc::ptr_eq(a, b)
+    }
+
+    fn try_unwrap(ptr: Self::Ptr) -> Result<InnerState<S, Self>, Self::Ptr> {
+        Rc::try_unwrap(ptr).map(RefCell::into_inner)
+    }
+
+    fn downgrade(ptr: &Self::Ptr) -> Self::Weak {
+        Rc::downgrade(ptr)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Ptr> {
+        weak.upgrade()
+    }
+}
+
+impl<S: Stream> Storage<S> for ThreadSafe {
+    type Ptr = Arc<RwLock<InnerState<S, Self>>>;
+    type Weak = sync::Weak<RwLock<InnerState<S, Self>>>;
+    type Observer = Box<dyn FnMut(&S::Item) + Send + Sync>;
+    type CloneFn = Box<dyn Fn(&S::Item) -> S::Item + Send + Sync>;
+
+    const NAME: &'static str = "Ashared";
+
+    fn new(state: InnerState<S, Self>) -> Self::Ptr {
+        Arc::new(RwLock::new(state))
+    }
+
+    fn read(ptr: &Self::Ptr) -> impl Deref<Target = InnerState<S, Self>> + '_ {
+        ptr.read()
+    }
+
+    fn try_read(ptr: &Self::Ptr) -> Option<impl Deref<Target = InnerState<S, Self>> + '_> {
+        ptr.try_read()
+    }
+
+    fn write(ptr: &Self::Ptr) -> impl DerefMut<Target = InnerState<S, Self>> + '_ {
+        ptr.write()
+    }
+
+    fn strong_count(ptr: &Self::Ptr) -> usize {
+        Arc::strong_count(ptr)
+    }
+
+    fn ptr_eq(a: &Self::Ptr, b: &Self::Ptr) -> bool {
+        Arc::ptr_eq(a, b)
+    }
+
+    fn try_unwrap(ptr: Self::Ptr) -> Result<InnerState<S, Self>, Self::Ptr> {
+        Arc::try_unwrap(ptr).map(RwLock::into_inner)
+    }
+
+    fn downgrade(ptr: &Self::Ptr) -> Self::Weak {
+        Arc::downgrade(ptr)
+    }
+
+    fn upgrade(weak: &Self::Weak) -> Option<Self::Ptr> {
+        weak.upgrade()
+    }
+}
@@ -0,0 +1,19 @@
+//! Checks that the crate can be used from a `no_std` crate. Run with
+//! `cargo test --no-default-features --features spin`.
+#![no_std]
+
+extern crate alloc;
+// only needed by the test harness
+extern crate std;
+
+use alloc::vec::Vec;
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+use shared_stream::Share;
+
+#[test]
+fn ashared_without_std() {
+    let shared = stream::iter(1..=3).ashared();
+    assert_eq!(block_on(shared.clone().take(1).collect::<Vec<_>>()), [1]);
+    assert_eq!(block_on(shared.collect::<Vec<_>>()), [1, 2, 3]);
+}